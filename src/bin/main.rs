@@ -55,12 +55,12 @@ async fn main() {
             }
         }
 
-        if c.fields.len() > 0 {
+        if !c.fields.is_empty() {
             println!("  Fields:");
             for field in c.fields.iter() {
                 println!("  -> Name: {}", field.name);
                 println!("     Descriptor: {:?}", field.descriptor);
-                println!("     Access: {}", field.access_flags.bits().to_string());
+                println!("     Access: {}", field.access_flags.bits());
 
                 for attr in field.attributes.iter() {
                     match attr {
@@ -71,7 +71,7 @@ async fn main() {
                             println!("     -> [Error]: msg: {}", error_attribute.message)
                         }
                         xjvmdbg::java_class::AttributeType::ConstantValue(cval) => {
-                            println!("     -> Constant value: {}", cval.to_string())
+                            println!("     -> Constant value: {}", cval)
                         }
                         _ => {
                             println!("     -> Invalid attribute (not expected on a field)")
@@ -83,12 +83,12 @@ async fn main() {
             println!("  Fields: none");
         }
 
-        if c.methods.len() > 0 {
+        if !c.methods.is_empty() {
             println!("  Methods:");
             for method in c.methods.iter() {
                 println!("  -> Name: {}", method.name);
                 println!("     Descriptor: {:?}", method.descriptor);
-                println!("     Access: {}", method.access_flags.bits().to_string());
+                println!("     Access: {}", method.access_flags.bits());
 
                 for attr in method.attributes.iter() {
                     match attr {
@@ -156,17 +156,19 @@ async fn main() {
     }
 
     let stream = TcpStream::connect("127.0.0.1:47239").await.unwrap();
-    let mut client = JdwpClient::new(stream).await.unwrap();
-    let version = client.vm_get_version().await.unwrap();
-    let id_sizes = client.vm_get_id_sizes().await.unwrap();
-    client.get_id_sizes().await.unwrap();
+    let client = JdwpClient::new(stream).await.unwrap();
+    let session = client.session();
 
-    println!("Version: {:?}", version);
-    println!("ID sizes: {:?}", id_sizes);
+    println!("Version: {:?}", session.version);
+    println!("ID sizes: {:?}", session.id_sizes);
+    println!("Capabilities: {:?}", session.capabilities);
 
     let classes = client.vm_get_all_classes().await.unwrap();
     println!("Loaded classes: ");
     for c in classes.classes {
         println!("{:?}", c);
     }
+
+    // Leave the VM running
+    client.close().await.unwrap();
 }
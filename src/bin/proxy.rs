@@ -0,0 +1,403 @@
+use binrw::BinRead;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use xjvmdbg::jdwp::{
+    Command, CommandPacketHeader, EventComposite, IdSizesArgs, JdwpCommand, JdwpErrorCode,
+    JdwpIdSizes, REPLY_FLAG, ReplyPacketHeader, array_reference, class_type, event_request,
+    interface_type, method, object_reference, reference_type, stack_frame, string_reference,
+    thread_reference, virtual_machine,
+};
+
+const HANDSHAKE_LENGTH: usize = 14;
+
+/// What the proxy has learned about a session from the packets going through it.
+#[derive(Default)]
+struct Session {
+    /// Commands sent by the debugger which have not been answered yet, by packet id.
+    pending: HashMap<u32, Command>,
+    /// Needed to decode most replies and events. Learned from the reply to IDSizes.
+    id_sizes: Option<JdwpIdSizes>,
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Debugger,
+    Vm,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <listen address> <VM address>", args[0]);
+        eprintln!("Example: {} 127.0.0.1:5006 127.0.0.1:5005", args[0]);
+        std::process::exit(2);
+    }
+
+    let listener = TcpListener::bind(&args[1]).await?;
+    println!("Waiting for a debugger on {}", listener.local_addr()?);
+    loop {
+        let (debugger, address) = listener.accept().await?;
+        println!("Debugger connected from {}", address);
+
+        let vm_address = args[2].clone();
+        tokio::spawn(async move {
+            match proxy_session(debugger, &vm_address).await {
+                Ok(()) => println!("Session from {} closed", address),
+                Err(e) => eprintln!("Session from {} failed: {}", address, e),
+            }
+        });
+    }
+}
+
+async fn proxy_session(mut debugger: TcpStream, vm_address: &str) -> io::Result<()> {
+    let mut vm = TcpStream::connect(vm_address).await?;
+
+    // The debugger sends the handshake and the VM echoes it
+    let mut handshake = [0u8; HANDSHAKE_LENGTH];
+    debugger.read_exact(&mut handshake).await?;
+    vm.write_all(&handshake).await?;
+    vm.read_exact(&mut handshake).await?;
+    debugger.write_all(&handshake).await?;
+    println!("Handshake: {}", String::from_utf8_lossy(&handshake));
+
+    let session = Arc::new(Mutex::new(Session::default()));
+    let (debugger_reader, debugger_writer) = tokio::io::split(debugger);
+    let (vm_reader, vm_writer) = tokio::io::split(vm);
+
+    let to_vm = forward(debugger_reader, vm_writer, Side::Debugger, session.clone());
+    let to_debugger = forward(vm_reader, debugger_writer, Side::Vm, session);
+    // Stop as soon as either side hangs up
+    tokio::select! {
+        result = to_vm => result,
+        result = to_debugger => result,
+    }
+}
+
+async fn forward(
+    mut reader: ReadHalf<TcpStream>,
+    mut writer: WriteHalf<TcpStream>,
+    from: Side,
+    session: Arc<Mutex<Session>>,
+) -> io::Result<()> {
+    loop {
+        let mut packet = vec![0u8; CommandPacketHeader::get_length()];
+        match reader.read_exact(&mut packet).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let length = u32::from_be_bytes(packet[0..4].try_into().unwrap()) as usize;
+        let header_length = packet.len();
+        packet.resize(length.max(header_length), 0);
+        reader.read_exact(&mut packet[header_length..]).await?;
+
+        // Forward first so that logging never delays the session
+        writer.write_all(&packet).await?;
+        log_packet(&packet, from, &mut session.lock().unwrap());
+    }
+}
+
+fn log_packet(packet: &[u8], from: Side, session: &mut Session) {
+    let (header, data) = packet.split_at(CommandPacketHeader::get_length());
+    let mut cursor = Cursor::new(header);
+
+    if header[8] & REPLY_FLAG != 0 {
+        let Ok(header) = ReplyPacketHeader::read_be(&mut cursor) else {
+            println!("<- Unparsable reply header {:02x?}", header);
+            return;
+        };
+        let command = session.pending.remove(&header.id);
+        let name = command
+            .map(|command| format!("{:?}", command))
+            .unwrap_or_else(|| "unknown command".to_string());
+        if !header.is_success() {
+            println!(
+                "<- #{} {}: {}",
+                header.id,
+                name,
+                JdwpErrorCode::from(header.error_code)
+            );
+            return;
+        }
+
+        let decoded = command.and_then(|command| describe_reply(command, data, session));
+        println!(
+            "<- #{} {}: {}",
+            header.id,
+            name,
+            decoded.unwrap_or_else(|| describe_raw(data))
+        );
+        return;
+    }
+
+    let Ok(header) = CommandPacketHeader::read_be(&mut cursor) else {
+        println!("   Unknown command {:02x?} {}", header, describe_raw(data));
+        return;
+    };
+    match from {
+        Side::Debugger => {
+            session.pending.insert(header.id, header.command);
+            let decoded = describe_request(header.command, data, session);
+            println!(
+                "-> #{} {:?} {}",
+                header.id,
+                header.command,
+                decoded.unwrap_or_else(|| describe_raw(data))
+            );
+        }
+        Side::Vm => {
+            let decoded = session.id_sizes.and_then(|sizes| {
+                EventComposite::read_be_args(&mut Cursor::new(data), sizes)
+                    .ok()
+                    .map(|composite| format!("{:?}", composite))
+            });
+            println!(
+                "<= #{} {:?}: {}",
+                header.id,
+                header.command,
+                decoded.unwrap_or_else(|| describe_raw(data))
+            );
+        }
+    }
+}
+
+/// Decodes the body of a `command` sent by the debugger. Returns `None` for commands
+/// without a body or whose body cannot be decoded on its own, e.g. because it holds
+/// untagged values, and when the ID sizes needed for it are not known yet.
+fn describe_request(command: Command, data: &[u8], session: &Session) -> Option<String> {
+    let sizes = session.id_sizes;
+    match command {
+        Command::VirtualMachineClassesBySignature => {
+            request::<virtual_machine::ClassesBySignature>(data, sizes)
+        }
+        Command::VirtualMachineExit => request::<virtual_machine::Exit>(data, sizes),
+        Command::VirtualMachineDisposeObjects => {
+            request::<virtual_machine::DisposeObjects>(data, sizes)
+        }
+        // Commands sharing a request type are decoded through one of them
+        Command::ReferenceTypeSignature
+        | Command::ReferenceTypeClassLoader
+        | Command::ReferenceTypeModifiers
+        | Command::ReferenceTypeFields
+        | Command::ReferenceTypeMethods
+        | Command::ReferenceTypeSourceFile
+        | Command::ReferenceTypeStatus
+        | Command::ReferenceTypeInterfaces
+        | Command::ReferenceTypeClassObject
+        | Command::ReferenceTypeFieldsWithGeneric
+        | Command::ReferenceTypeMethodsWithGeneric => {
+            request::<reference_type::Signature>(data, sizes)
+        }
+        Command::ReferenceTypeGetValues => request::<reference_type::GetValues>(data, sizes),
+        Command::ClassTypeInvokeMethod
+        | Command::ClassTypeNewInstance
+        | Command::InterfaceTypeInvokeMethod => request::<class_type::InvokeMethod>(data, sizes),
+        Command::MethodLineTable
+        | Command::MethodVariableTable
+        | Command::MethodVariableTableWithGeneric
+        | Command::MethodBytecodes => request::<method::LineTable>(data, sizes),
+        Command::ObjectReferenceReferenceType
+        | Command::ObjectReferenceMonitorInfo
+        | Command::ObjectReferenceDisableCollection
+        | Command::ObjectReferenceEnableCollection
+        | Command::StringReferenceValue
+        | Command::ArrayReferenceLength => request::<object_reference::ReferenceType>(data, sizes),
+        Command::ObjectReferenceGetValues => request::<object_reference::GetValues>(data, sizes),
+        Command::ObjectReferenceInvokeMethod => {
+            request::<object_reference::InvokeMethod>(data, sizes)
+        }
+        Command::ThreadReferenceName
+        | Command::ThreadReferenceSuspend
+        | Command::ThreadReferenceResume
+        | Command::ThreadReferenceStatus
+        | Command::ThreadReferenceThreadGroup
+        | Command::ThreadReferenceFrameCount
+        | Command::ThreadReferenceOwnedMonitors
+        | Command::ThreadReferenceCurrentContendedMonitor
+        | Command::ThreadReferenceSuspendCount => request::<thread_reference::Name>(data, sizes),
+        Command::ThreadReferenceFrames => request::<thread_reference::Frames>(data, sizes),
+        Command::ThreadReferenceForceEarlyReturn => {
+            request::<thread_reference::ForceEarlyReturn>(data, sizes)
+        }
+        Command::ArrayReferenceGetValues => request::<array_reference::GetValues>(data, sizes),
+        Command::EventRequestSet => request::<event_request::Set>(data, sizes),
+        Command::EventRequestClear => request::<event_request::Clear>(data, sizes),
+        Command::StackFrameGetValues => request::<stack_frame::GetValues>(data, sizes),
+        Command::StackFrameSetValues => request::<stack_frame::SetValues>(data, sizes),
+        Command::StackFrameThisObject | Command::StackFramePopFrames => {
+            request::<stack_frame::ThisObject>(data, sizes)
+        }
+        // The class files of RedefineClasses are too long to be shown
+        _ => None,
+    }
+}
+
+/// Decodes the body of a reply to `command`. Returns `None` for commands whose replies
+/// are empty, or when the ID sizes needed for the reply are not known yet.
+fn describe_reply(command: Command, data: &[u8], session: &mut Session) -> Option<String> {
+    if command == Command::VirtualMachineIDSizes {
+        let sizes = virtual_machine::IdSizes::decode_reply(data, None).ok()?;
+        let size = |size: i32| size.try_into().ok();
+        session.id_sizes = Some(JdwpIdSizes {
+            field_id_size: size(sizes.field_id_size)?,
+            method_id_size: size(sizes.method_id_size)?,
+            object_id_size: size(sizes.object_id_size)?,
+            reference_type_id_size: size(sizes.reference_type_id_size)?,
+            frame_id_size: size(sizes.frame_id_size)?,
+        });
+        return Some(format!("{:?}", sizes));
+    }
+
+    let sizes = session.id_sizes;
+    match command {
+        Command::VirtualMachineVersion => decode::<virtual_machine::Version>(data, sizes),
+        Command::VirtualMachineClassesBySignature => {
+            decode::<virtual_machine::ClassesBySignature>(data, sizes)
+        }
+        Command::VirtualMachineAllClasses => decode::<virtual_machine::AllClasses>(data, sizes),
+        Command::VirtualMachineAllThreads => decode::<virtual_machine::AllThreads>(data, sizes),
+        Command::VirtualMachineTopLevelThreadGroups => {
+            decode::<virtual_machine::TopLevelThreadGroups>(data, sizes)
+        }
+        Command::VirtualMachineCapabilities => decode::<virtual_machine::Capabilities>(data, sizes),
+        Command::VirtualMachineCapabilitiesNew => {
+            decode::<virtual_machine::CapabilitiesNew>(data, sizes)
+        }
+        Command::ReferenceTypeSignature => decode::<reference_type::Signature>(data, sizes),
+        Command::ReferenceTypeClassLoader => decode::<reference_type::ClassLoader>(data, sizes),
+        Command::ReferenceTypeFields => decode::<reference_type::Fields>(data, sizes),
+        Command::ReferenceTypeMethods => decode::<reference_type::Methods>(data, sizes),
+        Command::ReferenceTypeGetValues => decode::<reference_type::GetValues>(data, sizes),
+        Command::ReferenceTypeSourceFile => decode::<reference_type::SourceFile>(data, sizes),
+        Command::ReferenceTypeStatus => decode::<reference_type::Status>(data, sizes),
+        Command::ReferenceTypeInterfaces => decode::<reference_type::Interfaces>(data, sizes),
+        Command::ReferenceTypeClassObject => decode::<reference_type::ClassObject>(data, sizes),
+        Command::ReferenceTypeFieldsWithGeneric => {
+            decode::<reference_type::FieldsWithGeneric>(data, sizes)
+        }
+        Command::ReferenceTypeMethodsWithGeneric => {
+            decode::<reference_type::MethodsWithGeneric>(data, sizes)
+        }
+        Command::ClassTypeInvokeMethod => decode::<class_type::InvokeMethod>(data, sizes),
+        Command::ClassTypeNewInstance => decode::<class_type::NewInstance>(data, sizes),
+        Command::InterfaceTypeInvokeMethod => decode::<interface_type::InvokeMethod>(data, sizes),
+        Command::MethodLineTable => decode::<method::LineTable>(data, sizes),
+        // The generic signatures are not shown
+        Command::MethodVariableTable | Command::MethodVariableTableWithGeneric => {
+            decode::<method::VariableTable>(data, sizes)
+        }
+        Command::MethodBytecodes => decode::<method::Bytecodes>(data, sizes),
+        Command::ObjectReferenceReferenceType => {
+            decode::<object_reference::ReferenceType>(data, sizes)
+        }
+        Command::ObjectReferenceGetValues => decode::<object_reference::GetValues>(data, sizes),
+        Command::ObjectReferenceMonitorInfo => decode::<object_reference::MonitorInfo>(data, sizes),
+        Command::ObjectReferenceInvokeMethod => {
+            decode::<object_reference::InvokeMethod>(data, sizes)
+        }
+        Command::StringReferenceValue => decode::<string_reference::Value>(data, sizes),
+        Command::ThreadReferenceName => decode::<thread_reference::Name>(data, sizes),
+        Command::ThreadReferenceStatus => decode::<thread_reference::Status>(data, sizes),
+        Command::ThreadReferenceThreadGroup => decode::<thread_reference::ThreadGroup>(data, sizes),
+        Command::ThreadReferenceFrames => decode::<thread_reference::Frames>(data, sizes),
+        Command::ThreadReferenceFrameCount => decode::<thread_reference::FrameCount>(data, sizes),
+        Command::ThreadReferenceOwnedMonitors => {
+            decode::<thread_reference::OwnedMonitors>(data, sizes)
+        }
+        Command::ThreadReferenceCurrentContendedMonitor => {
+            decode::<thread_reference::CurrentContendedMonitor>(data, sizes)
+        }
+        Command::ThreadReferenceSuspendCount => {
+            decode::<thread_reference::SuspendCount>(data, sizes)
+        }
+        Command::StackFrameGetValues => decode::<stack_frame::GetValues>(data, sizes),
+        Command::StackFrameThisObject => decode::<stack_frame::ThisObject>(data, sizes),
+        Command::ArrayReferenceLength => decode::<array_reference::Length>(data, sizes),
+        Command::ArrayReferenceGetValues => decode::<array_reference::GetValues>(data, sizes),
+        Command::EventRequestSet => decode::<event_request::Set>(data, sizes),
+        _ => None,
+    }
+}
+
+fn request<C>(data: &[u8], sizes: Option<JdwpIdSizes>) -> Option<String>
+where
+    C: JdwpCommand<Request: Debug + for<'a> BinRead<Args<'a>: IdSizesArgs>>,
+{
+    C::decode_request(data, sizes)
+        .ok()
+        .map(|request| format!("{:?}", request))
+}
+
+fn decode<C: JdwpCommand<Reply: Debug>>(data: &[u8], sizes: Option<JdwpIdSizes>) -> Option<String> {
+    C::decode_reply(data, sizes)
+        .ok()
+        .map(|reply| format!("{:?}", reply))
+}
+
+fn describe_raw(data: &[u8]) -> String {
+    const MAX_BYTES: usize = 64;
+    if data.len() > MAX_BYTES {
+        format!("{} bytes {:02x?}...", data.len(), &data[..MAX_BYTES])
+    } else {
+        format!("{} bytes {:02x?}", data.len(), data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID_SIZES: [u8; 20] = [0, 0, 0, 8, 0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 8];
+
+    #[test]
+    fn test_id_sizes_reply_is_captured() {
+        let mut session = Session::default();
+        let described = describe_reply(Command::VirtualMachineIDSizes, &ID_SIZES, &mut session);
+        assert!(described.unwrap().contains("object_id_size: 4"));
+        let sizes = session.id_sizes.unwrap();
+        assert_eq!(sizes.object_id_size, 4);
+        assert_eq!(sizes.frame_id_size, 8);
+    }
+
+    #[test]
+    fn test_reply_needs_id_sizes() {
+        let mut session = Session::default();
+        let reply = [0, 0, 0, 1, 0, 0, 0, 42]; // 1 thread
+        assert!(describe_reply(Command::VirtualMachineAllThreads, &reply, &mut session).is_none());
+
+        describe_reply(Command::VirtualMachineIDSizes, &ID_SIZES, &mut session);
+        let described =
+            describe_reply(Command::VirtualMachineAllThreads, &reply, &mut session).unwrap();
+        assert!(described.contains("value: 42"));
+    }
+
+    #[test]
+    fn test_request_is_decoded() {
+        let mut session = Session::default();
+        describe_reply(Command::VirtualMachineIDSizes, &ID_SIZES, &mut session);
+
+        let mut request = vec![2, 2, 0, 0, 0, 1]; // Breakpoint, all, 1 modifier
+        request.extend([5, 0, 0, 0, 3]); // ClassMatch
+        request.extend(b"Foo");
+        let described = describe_request(Command::EventRequestSet, &request, &session).unwrap();
+        assert!(described.contains("ClassMatch(\"Foo\")"));
+
+        let described =
+            describe_request(Command::ThreadReferenceResume, &[0, 0, 0, 7], &session).unwrap();
+        assert!(described.contains("value: 7"));
+    }
+
+    #[test]
+    fn test_untagged_values_are_not_decoded() {
+        let mut session = Session::default();
+        describe_reply(Command::VirtualMachineIDSizes, &ID_SIZES, &mut session);
+
+        let request = [0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 5];
+        assert!(describe_request(Command::ObjectReferenceSetValues, &request, &session).is_none());
+    }
+}
@@ -405,10 +405,10 @@ fn read_table_switch<R: Read + Seek>(reader: &mut R) -> binrw::BinResult<Instruc
     }
 
     Ok(Instruction::Tableswitch {
-        default: default,
-        low: low,
-        high: high,
-        offsets: offsets,
+        default,
+        low,
+        high,
+        offsets,
     })
 }
 
@@ -1117,6 +1117,7 @@ mod tests {
 
     // Helper test to verify padding calculation
     #[test]
+    #[allow(clippy::identity_op)]
     fn test_padding_calculation() {
         // Test the padding formula: (4 - ((pos + 1) % 4)) % 4
         assert_eq!((4 - ((0 + 1) % 4)) % 4, 3); // pos=0 -> 3 padding bytes
@@ -53,9 +53,7 @@ impl FieldDescriptor {
     }
     pub fn from_class(class_name: String) -> Self {
         Self {
-            element_type: ComponentType::Object {
-                class_name: class_name,
-            },
+            element_type: ComponentType::Object { class_name },
             array_dimension: None,
         }
     }
@@ -22,18 +22,18 @@ pub enum ConstantAttribute {
     Double(f64),
     String(String),
 }
-impl ToString for ConstantAttribute {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ConstantAttribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ConstantAttribute::Int(int) => int.to_string(),
-            ConstantAttribute::Short(short) => short.to_string(),
-            ConstantAttribute::Char(char) => char.to_string(),
-            ConstantAttribute::Byte(byte) => byte.to_string(),
-            ConstantAttribute::Boolean(bool) => bool.to_string(),
-            ConstantAttribute::Float(float) => float.to_string(),
-            ConstantAttribute::Long(long) => long.to_string(),
-            ConstantAttribute::Double(double) => double.to_string(),
-            ConstantAttribute::String(string) => format!("\"{}\"", string),
+            ConstantAttribute::Int(int) => write!(f, "{}", int),
+            ConstantAttribute::Short(short) => write!(f, "{}", short),
+            ConstantAttribute::Char(char) => write!(f, "{}", char),
+            ConstantAttribute::Byte(byte) => write!(f, "{}", byte),
+            ConstantAttribute::Boolean(bool) => write!(f, "{}", bool),
+            ConstantAttribute::Float(float) => write!(f, "{}", float),
+            ConstantAttribute::Long(long) => write!(f, "{}", long),
+            ConstantAttribute::Double(double) => write!(f, "{}", double),
+            ConstantAttribute::String(string) => write!(f, "\"{}\"", string),
         }
     }
 }
@@ -79,15 +79,10 @@ impl CodeAttribute {
     where
         R: Read + Seek,
     {
-        let raw_r = CodeAttributeRaw::read(reader);
-        if raw_r.is_err() {
-            return Err(AttributeReadError::Deserialization(raw_r.unwrap_err()));
-        }
-
-        let raw = raw_r.unwrap();
+        let raw = CodeAttributeRaw::read(reader).map_err(AttributeReadError::Deserialization)?;
         let mut attributes = Vec::with_capacity(raw.attributes.len());
         for attr_info in raw.attributes {
-            let attr = JavaClassContainerBuilder::parse_attribute(&attr_info, &raw_class);
+            let attr = JavaClassContainerBuilder::parse_attribute(&attr_info, raw_class);
             attributes.push(attr);
         }
 
@@ -97,7 +92,7 @@ impl CodeAttribute {
             max_locals: raw.max_locals,
             code: raw.code,
             exception_table: raw.exception_table,
-            attributes: attributes,
+            attributes,
         })
     }
 }
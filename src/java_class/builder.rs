@@ -23,14 +23,11 @@ impl<'a> JavaClassContainerBuilder<'a> {
     }
 
     fn find_class(&self, name: &str) -> Option<&Rc<JavaClass>> {
-        let result = self.classes.iter().find(|(class_name, _class)| {
-            return *class_name == name;
-        });
-        if result.is_some() {
-            return Some(result.unwrap().1);
-        }
-
-        None
+        let result = self
+            .classes
+            .iter()
+            .find(|(class_name, _class)| *class_name == name);
+        result.map(|(_, class)| class)
     }
 
     fn parse_super_class(&mut self, class: &mut JavaClass, raw_class: &JavaClassFile) {
@@ -61,7 +58,7 @@ impl<'a> JavaClassContainerBuilder<'a> {
                         Some(dummy_rc)
                     }
                 }
-                Some(parsed_super) => Some(Rc::clone(&parsed_super)),
+                Some(parsed_super) => Some(Rc::clone(parsed_super)),
             };
         }
     }
@@ -73,12 +70,12 @@ impl<'a> JavaClassContainerBuilder<'a> {
     ) -> Result<AttributeType, AttributeReadError> {
         let mut cursor = Cursor::new(data);
         match attr_name {
-            "Code" => CodeAttribute::read(&mut cursor, &raw_class).map(AttributeType::Code),
+            "Code" => CodeAttribute::read(&mut cursor, raw_class).map(AttributeType::Code),
             "ConstantValue" => ConstantValueAttributeRaw::read(&mut cursor)
                 .map(AttributeType::ConstantValueIndex)
                 .map_err(AttributeReadError::Deserialization),
             "Deprecated" => Ok(AttributeType::Deprecated),
-            "SourceFile" => SourceFileAttribute::read(&mut cursor, &raw_class)
+            "SourceFile" => SourceFileAttribute::read(&mut cursor, raw_class)
                 .map(AttributeType::SourceFile)
                 .map_err(AttributeReadError::Deserialization),
             _ => Result::Err(AttributeReadError::NotSuported),
@@ -102,7 +99,7 @@ impl<'a> JavaClassContainerBuilder<'a> {
             Err(e) => match e {
                 AttributeReadError::Deserialization(error) => {
                     AttributeType::Error(ErrorAttribute {
-                        message: format!("Deserialization error: {}", error.to_string()),
+                        message: format!("Deserialization error: {}", error),
                         data: attribute_info.data.clone(),
                     })
                 }
@@ -206,15 +203,15 @@ impl<'a> JavaClassContainerBuilder<'a> {
             println!("Field: {} (d: {})", name, descriptor_raw_string);
 
             let descriptor = crate::descriptors::parse_field_descriptor(descriptor_raw_string);
-            if descriptor.is_err() {
-                println!(
-                    "Could not parse field descriptor: {:?}",
-                    descriptor.unwrap_err()
-                );
-                continue;
-            }
+            let descriptor = match descriptor {
+                Ok(descriptor) => descriptor,
+                Err(e) => {
+                    println!("Could not parse field descriptor: {:?}", e);
+                    continue;
+                }
+            };
 
-            let mut field = Field::new(field_info.access_flags, name, descriptor.unwrap());
+            let mut field = Field::new(field_info.access_flags, name, descriptor);
 
             // parse attributes
             for a_info in field_info.attributes.iter() {
@@ -259,15 +256,15 @@ impl<'a> JavaClassContainerBuilder<'a> {
             println!("Method: {} (d: {})", name, descriptor_raw);
 
             let descriptor = crate::descriptors::parse_method_descriptor(descriptor_raw);
-            if descriptor.is_err() {
-                println!(
-                    "Could not parse method descriptor: {:?}",
-                    descriptor.unwrap_err()
-                );
-                continue;
-            }
+            let descriptor = match descriptor {
+                Ok(descriptor) => descriptor,
+                Err(e) => {
+                    println!("Could not parse method descriptor: {:?}", e);
+                    continue;
+                }
+            };
 
-            let mut method = Method::new(method_info.access_flags, name, descriptor.unwrap());
+            let mut method = Method::new(method_info.access_flags, name, descriptor);
 
             // parse attributes
             for a_info in method_info.attributes.iter() {
@@ -300,13 +297,13 @@ impl<'a> JavaClassContainerBuilder<'a> {
             return Rc::clone(c);
         }
 
-        let mut class = JavaClass::new(raw_class.version.clone(), name.to_string());
+        let mut class = JavaClass::new(raw_class.version, name.to_string());
         self.parse_super_class(&mut class, raw_class);
 
         if let Err(attr_error) = self.parse_class_attributes(&mut class, raw_class) {
             class.attributes.clear();
             class.attributes.push(AttributeType::Error(ErrorAttribute {
-                message: format!("Could not parse attributes: {}", attr_error.to_string()),
+                message: format!("Could not parse attributes: {}", attr_error),
                 data: vec![],
             }));
         }
@@ -2,13 +2,13 @@ pub(crate) enum AttributeReadError {
     Deserialization(binrw::Error),
     NotSuported,
 }
-impl ToString for AttributeReadError {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for AttributeReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AttributeReadError::Deserialization(error) => {
-                format!("Deserialization error: {}", error.to_string())
+                write!(f, "Deserialization error: {}", error)
             }
-            AttributeReadError::NotSuported => String::from("Not supported"),
+            AttributeReadError::NotSuported => write!(f, "Not supported"),
         }
     }
 }
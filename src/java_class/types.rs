@@ -18,7 +18,7 @@ impl Field {
         Field {
             access_flags,
             name: String::from(name),
-            descriptor: descriptor,
+            descriptor,
             attributes: vec![],
         }
     }
@@ -35,7 +35,7 @@ impl Method {
         Method {
             access_flags,
             name: String::from(name),
-            descriptor: descriptor,
+            descriptor,
             attributes: vec![],
         }
     }
@@ -2,7 +2,6 @@ use binrw::{BinRead, BinResult, BinWrite, Endian, binrw};
 use bitflags::bitflags;
 use byteorder::ReadBytesExt;
 use std::io::{Read, Seek, Write};
-use std::u16;
 
 const JAVA_CLASS_FILE_MAGIC: u32 = 0xCAFEBABE;
 
@@ -13,8 +12,8 @@ pub struct Version {
     minor: u16,
     major: u16,
 }
-impl Version {
-    pub fn default() -> Self {
+impl Default for Version {
+    fn default() -> Self {
         Self {
             magic: JAVA_CLASS_FILE_MAGIC,
             major: 52,
@@ -499,8 +498,14 @@ pub struct JavaClassFile {
 
 impl JavaClassFile {
     pub fn get_name(&self) -> &str {
-        let class_info = self.constant_pool.find_class(self.this_class).unwrap();
-        self.constant_pool.find_utf8(class_info.name_index).unwrap()
+        self.find_name().unwrap()
+    }
+
+    /// The internal name of this class, or `None` if `this_class` does not point to a
+    /// class entry in the constant pool.
+    pub fn find_name(&self) -> Option<&str> {
+        let class_info = self.constant_pool.find_class(self.this_class)?;
+        self.constant_pool.find_utf8(class_info.name_index)
     }
 }
 
@@ -1,25 +1,244 @@
-use binrw::meta::ReadEndian;
 use binrw::{BinRead, BinWrite};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::io;
 use std::io::Cursor;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, Weak};
 use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
-use tokio::sync::{Mutex, oneshot};
-use tokio::time::timeout;
+use tokio::sync::{Mutex, OnceCell, mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
 
+use crate::java_class_file::{ClassAccessFlags, JavaClassFile};
 use crate::jdwp::{
-    AllClassesReply, Command, CommandPacketHeader, IdSizesReply, JdwpIdSizes, ReplyPacketHeader,
-    VersionReply, result,
+    AllClassesReply, AllThreadsReply, ArrayGetValuesRequest, ArrayRegion, ArraySetValuesRequest,
+    BytecodesReply, Capabilities, CapabilitiesReply, ClassDefinition, ClassLoaderId, ClassObjectId,
+    ClassStatus, ClassesBySignatureReply, ClassesBySignatureRequest, Command, CommandPacketHeader,
+    DisposeObjectsRequest, Event, EventComposite, EventKind, EventModifier, EventRequestClear,
+    EventRequestSet, FieldId, FieldValue, FieldsReply, FieldsWithGenericReply,
+    ForceEarlyReturnRequest, FrameId, FrameSlot, IdSizesReply, InterfacesReply, InvokeMethodReply,
+    InvokeOptions, JdwpCommand, JdwpIdSize, JdwpIdSizes, JdwpString, LineTableReply, Location,
+    MethodId, MethodRequest, MethodsReply, MethodsWithGenericReply, MonitorInfoReply,
+    NewInstanceReply, ObjectGetValuesRequest, ObjectId, ObjectInvokeRequest, ObjectReferenceCount,
+    ObjectReferenceTypeReply, ObjectSetValuesRequest, OwnedMonitorsReply, REPLY_FLAG,
+    RedefineClassesRequest, ReferenceTypeField, ReferenceTypeGetValuesRequest, ReferenceTypeId,
+    ReplyPacketHeader, RequestId, SlotValue, StackFrameGetValuesRequest, StackFrameRequest,
+    StackFrameSetValuesRequest, StaticInvokeRequest, StepDepth, StepSize, SuspendPolicy,
+    SuspendStatus, TaggedObjectId, ThreadFramesReply, ThreadFramesRequest, ThreadGroupId, ThreadId,
+    ThreadStatus, ThreadStatusReply, TopLevelThreadGroupsReply, TypeTag, Value, ValuesReply,
+    VariableTableReply, VariableTableWithGenericReply, VersionReply, array_reference, class_type,
+    event_request, interface_type, method, object_reference, reference_type, result, stack_frame,
+    string_reference, thread_reference, virtual_machine,
 };
 
+/// A debugger connection to a VM.
+///
+/// Dropping a connected client sends VirtualMachine.Dispose from a spawned task. This is
+/// best effort: the task does not run if the runtime is shutting down, e.g. when
+/// returning from `#[tokio::main]`, and then the VM only sees the connection close.
+/// Use `close` to be sure.
 pub struct JdwpClient<T> {
+    inner: Arc<ClientInner<T>>,
+}
+
+/// The state of a client, shared with the background task which installs deferred line
+/// breakpoints.
+struct ClientInner<T> {
     writer: Arc<Mutex<WriteHalf<T>>>,
     pending_requests: Arc<Mutex<HashMap<u32, oneshot::Sender<ReplyPacket>>>>,
     packet_id: Arc<Mutex<u32>>,
-    _reader_handle: tokio::task::JoinHandle<()>,
-    sizes: Option<JdwpIdSizes>,
+    /// Reader and event tasks of the current connection.
+    connection_tasks: std::sync::Mutex<Vec<JoinHandle<()>>>,
+    sizes: watch::Sender<Option<JdwpIdSizes>>,
+    /// Counts the connections made so far. Requests are only sent on the connection they
+    /// were encoded for.
+    generation: AtomicU64,
+    state: Arc<watch::Sender<ConnectionState>>,
+    events_tx: mpsc::UnboundedSender<EventComposite>,
+    events: Mutex<mpsc::UnboundedReceiver<EventComposite>>,
+    event_waiters: Arc<std::sync::Mutex<HashMap<RequestId, oneshot::Sender<Event>>>>,
+    deferred_breakpoints: Mutex<HashMap<RequestId, DeferredBreakpoint>>,
+    session: RwLock<Option<Arc<SessionInfo>>>,
+    reconnect: Option<Reconnect<T>>,
+    object_references: std::sync::Mutex<ObjectReferences>,
+    /// Set by `vm_dispose`, after which the client neither sends requests nor reconnects.
+    disposed: AtomicBool,
+    /// Tries to send VirtualMachine.Dispose without waiting for the reply, for `Drop`.
+    dispose_on_drop: Box<dyn Fn() + Send + Sync>,
+}
+
+/// How long `step` waits for the thread to stop again, which may take a while when
+/// stepping over a call.
+const STEP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Disposed references are sent to the VM once this many have been collected, or when
+/// `JdwpClient::flush_object_references` is called.
+const DISPOSE_BATCH_SIZE: i32 = 64;
+
+/// An event registered with `JdwpClient::wait_for_event`. Dropping it unregisters the
+/// event again, e.g. when the request failed or the caller stopped waiting.
+struct EventWaiter<'a> {
+    waiters: &'a std::sync::Mutex<HashMap<RequestId, oneshot::Sender<Event>>>,
+    request_id: RequestId,
+    receiver: oneshot::Receiver<Event>,
+}
+
+impl Drop for EventWaiter<'_> {
+    fn drop(&mut self) {
+        self.waiters.lock().unwrap().remove(&self.request_id);
+    }
+}
+
+/// Objects whose lifetime the client manages, see `JdwpClient::pin_object` and
+/// `JdwpClient::dispose_object`.
+#[derive(Default)]
+struct ObjectReferences {
+    /// Objects with live `PinnedObject` guards.
+    pins: HashMap<ObjectId, ObjectPin>,
+    /// Objects whose last guard was dropped and which the VM may collect again.
+    unpinned: Vec<ObjectId>,
+    /// References given back with `dispose_object` which were not sent to the VM yet.
+    disposed: HashMap<ObjectId, i32>,
+}
+
+/// Collection is only disabled once per object, as the VM does not count DisableCollection
+/// commands.
+struct ObjectPin {
+    /// Number of live guards.
+    guards: usize,
+    /// Set once DisableCollection succeeded. Concurrent pins wait for the first one to
+    /// get there, or try again if it failed.
+    collection_disabled: Arc<OnceCell<()>>,
+}
+
+/// Keeps the VM from garbage collecting an object, so that its ID stays valid across
+/// requests. Collection is enabled again once the last guard for the object is dropped,
+/// or explicitly with `unpin`.
+///
+/// Dropping cannot send commands, so the VM is told along with the next request, or by
+/// `JdwpClient::flush_object_references`.
+pub struct PinnedObject<'a, T> {
+    client: &'a JdwpClient<T>,
+    object: ObjectId,
+}
+
+impl<T> PinnedObject<'_, T> {
+    pub fn id(&self) -> ObjectId {
+        self.object
+    }
+}
+
+impl<T> PinnedObject<'_, T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    /// Drops the guard and waits until the VM may collect the object again.
+    pub async fn unpin(self) -> result::Result<()> {
+        let client = self.client;
+        drop(self);
+        client.flush_object_references().await
+    }
+}
+
+impl<T> Drop for PinnedObject<'_, T> {
+    fn drop(&mut self) {
+        let mut references = self.client.inner.object_references.lock().unwrap();
+        if let Some(pin) = references.pins.get_mut(&self.object) {
+            pin.guards -= 1;
+            if pin.guards == 0 {
+                let collection_disabled = pin.collection_disabled.initialized();
+                references.pins.remove(&self.object);
+                // Nothing to undo if disabling collection failed
+                if collection_disabled {
+                    references.unpinned.push(self.object);
+                }
+            }
+        }
+    }
+}
+
+/// State of the connection to the VM, see `JdwpClient::connection_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// The VM sent a VMDeath event. The socket may still be open for a moment.
+    VmDead,
+    /// The socket was closed or failed.
+    Disconnected,
+}
+
+/// How a client created with `JdwpClient::connect_with_reconnect` gets the connection back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Connection attempts per reconnect, including the first one.
+    pub max_attempts: u32,
+    /// Pause between two attempts.
+    pub delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: 5,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+type Connector<T> =
+    Box<dyn Fn() -> Pin<Box<dyn Future<Output = io::Result<T>> + Send>> + Send + Sync>;
+
+struct Reconnect<T> {
+    connector: Connector<T>,
+    policy: ReconnectPolicy,
+    /// Makes concurrent requests share a single reconnect.
+    lock: Mutex<()>,
+}
+
+/// What the client learned about the VM while connecting. It does not change for the
+/// lifetime of the connection, but is negotiated again after a reconnect.
+#[derive(Debug)]
+pub struct SessionInfo {
+    pub id_sizes: JdwpIdSizes,
+    pub version: VersionReply,
+    pub capabilities: Capabilities,
+}
+
+/// Result of `JdwpClient::set_line_breakpoint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineBreakpoint {
+    /// Breakpoints were set at every location of the line, one per loaded class and method.
+    /// The ClassPrepare request `class_prepare` installs more in classes of the same name
+    /// loaded later, e.g. by another class loader.
+    Installed {
+        class_prepare: RequestId,
+        breakpoints: Vec<RequestId>,
+    },
+    /// The class is not loaded yet. The ID is that of the ClassPrepare request which
+    /// installs the breakpoint once the VM reports the class being prepared.
+    Deferred(RequestId),
+}
+
+/// A line breakpoint, keyed by its ClassPrepare request, which installs breakpoints in
+/// every class of the name that is or will be prepared.
+struct DeferredBreakpoint {
+    line_number: i32,
+    suspend_policy: SuspendPolicy,
+    /// Classes the breakpoint was installed in, or is being installed in.
+    classes: HashSet<ReferenceTypeId>,
+    installed: Vec<RequestId>,
+}
+
+/// Summary of a live thread, as returned by `JdwpClient::list_threads`.
+#[derive(Debug)]
+pub struct ThreadInfo {
+    pub thread: ThreadId,
+    pub name: String,
+    pub status: ThreadStatus,
+    pub suspend_status: SuspendStatus,
 }
 
 struct ReplyPacket {
@@ -27,80 +246,408 @@ struct ReplyPacket {
     data: Vec<u8>,
 }
 
+struct CommandPacket {
+    header: CommandPacketHeader,
+    data: Vec<u8>,
+}
+
+enum Packet {
+    Reply(ReplyPacket),
+    Command(CommandPacket),
+}
+
 impl<T> JdwpClient<T>
 where
     T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
-    pub async fn new(mut stream: T) -> result::Result<Self> {
+    pub async fn new(stream: T) -> result::Result<Self> {
+        Self::setup(stream, None).await
+    }
+
+    /// Connects with `connect` and does it again whenever the connection is lost, e.g.
+    /// for long-running sessions attached to a VM that may be restarted.
+    ///
+    /// A lost connection is re-established by the next request, which redoes the handshake
+    /// and negotiates the session again. IDs, event requests and deferred line breakpoints
+    /// do not survive this, so that request fails with `Error::Disconnected` if it contains
+    /// IDs, and event requests have to be set again.
+    pub async fn connect_with_reconnect<F, Fut>(
+        connect: F,
+        policy: ReconnectPolicy,
+    ) -> result::Result<Self>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<T>> + Send + 'static,
+    {
+        let stream = connect().await?;
+        let reconnect = Reconnect {
+            connector: Box::new(move || Box::pin(connect())),
+            policy,
+            lock: Mutex::new(()),
+        };
+        Self::setup(stream, Some(reconnect)).await
+    }
+
+    async fn setup(mut stream: T, reconnect: Option<Reconnect<T>>) -> result::Result<Self> {
         Self::do_handshake(&mut stream).await?;
 
         let (reader, writer) = tokio::io::split(stream);
-
-        let pending_requests = Arc::new(Mutex::new(HashMap::new()));
-        let writer_arc = Arc::new(Mutex::new(writer));
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let writer = Arc::new(Mutex::new(writer));
         let packet_id = Arc::new(Mutex::new(0));
 
-        // Spawn reader task
-        let pending_clone = pending_requests.clone();
+        let inner = ClientInner {
+            writer: writer.clone(),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            packet_id: packet_id.clone(),
+            connection_tasks: std::sync::Mutex::new(Vec::new()),
+            sizes: watch::Sender::new(None),
+            generation: AtomicU64::new(0),
+            state: Arc::new(watch::Sender::new(ConnectionState::Connected)),
+            events_tx,
+            events: Mutex::new(events_rx),
+            event_waiters: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            deferred_breakpoints: Mutex::new(HashMap::new()),
+            session: RwLock::new(None),
+            reconnect,
+            object_references: std::sync::Mutex::new(ObjectReferences::default()),
+            disposed: AtomicBool::new(false),
+            dispose_on_drop: Self::dispose_on_drop(writer, packet_id),
+        };
+        let client = JdwpClient {
+            inner: Arc::new(inner),
+        };
+        client.spawn_connection_tasks(reader);
+        client.negotiate_session(0).await?;
+
+        Ok(client)
+    }
+
+    fn dispose_on_drop(
+        writer: Arc<Mutex<WriteHalf<T>>>,
+        packet_id: Arc<Mutex<u32>>,
+    ) -> Box<dyn Fn() + Send + Sync> {
+        Box::new(move || {
+            // Without a runtime there is nothing to send with, and the VM sees the
+            // connection close instead
+            let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                return;
+            };
+            let writer = writer.clone();
+            let packet_id = packet_id.clone();
+            runtime.spawn(async move {
+                let id = {
+                    let mut id = packet_id.lock().await;
+                    *id = id.wrapping_add(1);
+                    *id
+                };
+                let header = CommandPacketHeader {
+                    length: CommandPacketHeader::get_length() as u32,
+                    id,
+                    flags: 0,
+                    command: virtual_machine::Dispose::COMMAND,
+                };
+                let mut writer = writer.lock().await;
+                let _ = Self::write_request(&mut *writer, &header, &[]).await;
+            });
+        })
+    }
+
+    /// Starts reading packets from a new connection, replacing the tasks of the previous one.
+    fn spawn_connection_tasks(&self, reader: ReadHalf<T>) {
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+
+        let pending_requests = self.inner.pending_requests.clone();
         let reader_handle = tokio::spawn(async move {
-            Self::reader_loop(reader, pending_clone).await;
+            Self::reader_loop(reader, pending_requests, command_tx).await;
         });
 
-        Ok(JdwpClient {
-            writer: writer_arc,
-            pending_requests,
-            packet_id,
-            _reader_handle: reader_handle,
-            sizes: None,
-        })
+        // Events can arrive before the ID sizes are known (e.g. VMStart right after the
+        // handshake), so they are decoded on a separate task which waits for them.
+        let sizes = self.inner.sizes.subscribe();
+        let events = self.inner.events_tx.clone();
+        let event_waiters = self.inner.event_waiters.clone();
+        let state = self.inner.state.clone();
+        let client = Arc::downgrade(&self.inner);
+        let event_handle = tokio::spawn(async move {
+            Self::event_loop(command_rx, sizes, events, event_waiters, state, client).await;
+        });
+
+        let mut tasks = self.inner.connection_tasks.lock().unwrap();
+        for task in tasks.drain(..) {
+            task.abort();
+        }
+        tasks.extend([reader_handle, event_handle]);
+    }
+
+    /// Queries the ID sizes, version and capabilities of the VM on the connection
+    /// `generation`. Commands whose bodies contain IDs cannot be sent before this is done.
+    async fn negotiate_session(&self, generation: u64) -> result::Result<()> {
+        let sizes = self
+            .send_on::<virtual_machine::IdSizes>(&(), generation)
+            .await?;
+        let size = |size: i32| -> result::Result<JdwpIdSize> {
+            match size.try_into() {
+                Ok(size @ 1..=8) => Ok(size),
+                _ => Err(result::Error::IdSizesTruncated),
+            }
+        };
+        let id_sizes = JdwpIdSizes {
+            field_id_size: size(sizes.field_id_size)?,
+            method_id_size: size(sizes.method_id_size)?,
+            object_id_size: size(sizes.object_id_size)?,
+            reference_type_id_size: size(sizes.reference_type_id_size)?,
+            frame_id_size: size(sizes.frame_id_size)?,
+        };
+
+        let version = self
+            .send_on::<virtual_machine::Version>(&(), generation)
+            .await?;
+        let capabilities = match self
+            .send_on::<virtual_machine::CapabilitiesNew>(&(), generation)
+            .await
+        {
+            // VMs older than JDWP 1.4
+            Err(result::Error::JdwpError(result::JdwpErrorCode::NotImplemented)) => self
+                .send_on::<virtual_machine::Capabilities>(&(), generation)
+                .await?
+                .into(),
+            other => other?,
+        };
+
+        *self.inner.session.write().unwrap() = Some(Arc::new(SessionInfo {
+            id_sizes,
+            version,
+            capabilities,
+        }));
+        self.inner.sizes.send_replace(Some(id_sizes));
+        Ok(())
+    }
+
+    /// The ID sizes, version and capabilities negotiated with the VM.
+    pub fn session(&self) -> Arc<SessionInfo> {
+        self.inner
+            .session
+            .read()
+            .unwrap()
+            .clone()
+            .expect("session is negotiated before the client is returned")
+    }
+
+    /// Watches the state of the connection, e.g. to find out when the VM went away without
+    /// waiting for a request to fail.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.inner.state.subscribe()
+    }
+
+    /// The error for requests which can no longer be answered.
+    fn connection_error(&self) -> result::Error {
+        match *self.inner.state.borrow() {
+            ConnectionState::VmDead => result::Error::VmDead,
+            _ => result::Error::Disconnected,
+        }
+    }
+
+    /// Fails unless the connection is usable, reconnecting first if the client has a
+    /// reconnect policy. Returns the generation of the connection.
+    async fn ensure_connected(&self) -> result::Result<u64> {
+        if self.inner.disposed.load(Ordering::Relaxed) {
+            return Err(result::Error::Disconnected);
+        }
+        let state = *self.inner.state.borrow();
+        match state {
+            ConnectionState::Connected => {}
+            ConnectionState::VmDead => return Err(result::Error::VmDead),
+            ConnectionState::Disconnected if self.inner.reconnect.is_some() => {
+                self.reconnect().await?
+            }
+            ConnectionState::Disconnected => return Err(result::Error::Disconnected),
+        }
+        Ok(self.inner.generation.load(Ordering::Acquire))
+    }
+
+    /// Connects to the VM again according to the reconnect policy. Does nothing if the
+    /// client is connected, and fails with `Error::Disconnected` if it has no policy.
+    pub async fn reconnect(&self) -> result::Result<()> {
+        let Some(reconnect) = &self.inner.reconnect else {
+            return Err(result::Error::Disconnected);
+        };
+        let _guard = reconnect.lock.lock().await;
+        if *self.inner.state.borrow() == ConnectionState::Connected {
+            return Ok(());
+        }
+
+        let mut last_error = result::Error::Disconnected;
+        for attempt in 0..reconnect.policy.max_attempts {
+            if attempt > 0 {
+                sleep(reconnect.policy.delay).await;
+            }
+            match self.try_reconnect(reconnect).await {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn try_reconnect(&self, reconnect: &Reconnect<T>) -> result::Result<()> {
+        let mut stream = (reconnect.connector)().await?;
+        Self::do_handshake(&mut stream).await?;
+        let (reader, writer) = tokio::io::split(stream);
+
+        // Nothing from the old connection is valid for the new one. The state stays
+        // `Disconnected` until the session is negotiated, so other requests wait for
+        // this reconnect to finish.
+        let generation = {
+            let mut current = self.inner.writer.lock().await;
+            *current = writer;
+            self.inner.sizes.send_replace(None);
+            self.inner.generation.fetch_add(1, Ordering::AcqRel) + 1
+        };
+        self.inner.pending_requests.lock().await.clear();
+        self.inner.event_waiters.lock().unwrap().clear();
+        self.inner.deferred_breakpoints.lock().await.clear();
+        *self.inner.object_references.lock().unwrap() = ObjectReferences::default();
+        self.spawn_connection_tasks(reader);
+
+        self.negotiate_session(generation).await?;
+        self.inner.state.send_replace(ConnectionState::Connected);
+        Ok(())
     }
 
     async fn reader_loop(
         mut reader: ReadHalf<T>,
         pending_requests: Arc<Mutex<HashMap<u32, oneshot::Sender<ReplyPacket>>>>,
+        commands: mpsc::UnboundedSender<CommandPacket>,
     ) {
         loop {
-            // TODO: Handle command packets coming from the VM
-            match Self::read_reply_packet(&mut reader).await {
-                Ok(reply_packet) => {
+            match Self::read_packet(&mut reader).await {
+                Ok(Packet::Reply(reply_packet)) => {
                     let mut pending = pending_requests.lock().await;
                     if let Some(sender) = pending.remove(&reply_packet.header.id) {
                         let _ = sender.send(reply_packet);
                     }
                 }
-                Err(e) => {
-                    eprintln!("Reader task error: {:?}", e);
-                    // Notify all pending requests about the error
-                    let mut pending = pending_requests.lock().await;
-                    for (_, sender) in pending.drain() {
-                        let _ = sender.send(ReplyPacket {
-                            header: ReplyPacketHeader::default(),
-                            data: Vec::new(),
+                Ok(Packet::Command(command_packet)) => {
+                    let _ = commands.send(command_packet);
+                }
+                Err(result::Error::ParsingError { message }) => {
+                    // The whole packet has been consumed, so the stream is still in sync
+                    log::warn!("Skipping unparsable packet: {}", message);
+                }
+                Err(_) => {
+                    // The connection is gone. Dropping the senders fails all pending
+                    // requests, and dropping `commands` lets the event task finish up.
+                    pending_requests.lock().await.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn event_loop(
+        mut commands: mpsc::UnboundedReceiver<CommandPacket>,
+        mut sizes: watch::Receiver<Option<JdwpIdSizes>>,
+        events: mpsc::UnboundedSender<EventComposite>,
+        event_waiters: Arc<std::sync::Mutex<HashMap<RequestId, oneshot::Sender<Event>>>>,
+        state: Arc<watch::Sender<ConnectionState>>,
+        client: Weak<ClientInner<T>>,
+    ) {
+        while let Some(command_packet) = commands.recv().await {
+            if command_packet.header.command != Command::EventComposite {
+                log::warn!(
+                    "Ignoring unexpected command from the VM: {:?}",
+                    command_packet.header.command
+                );
+                continue;
+            }
+
+            let Ok(id_sizes) = sizes.wait_for(Option::is_some).await.map(|s| s.unwrap()) else {
+                break;
+            };
+
+            let mut cursor = Cursor::new(&command_packet.data);
+            match EventComposite::read_be_args(&mut cursor, id_sizes) {
+                Ok(composite) => {
+                    if composite
+                        .events
+                        .iter()
+                        .any(|event| matches!(event, Event::VmDeath { .. }))
+                    {
+                        state.send_replace(ConnectionState::VmDead);
+                    }
+
+                    // Deferred line breakpoints are installed here, as nobody may be
+                    // calling `next_event`
+                    let Some(inner) = client.upgrade() else {
+                        break;
+                    };
+                    let composite = JdwpClient { inner }
+                        .take_deferred_class_prepares(composite)
+                        .await;
+
+                    // Events somebody is explicitly waiting for are not reported to the channel
+                    let mut waiters = event_waiters.lock().unwrap();
+                    let mut remaining = Vec::with_capacity(composite.events.len());
+                    for event in composite.events {
+                        match waiters.remove(&event.request_id()) {
+                            Some(waiter) => {
+                                if let Err(event) = waiter.send(event) {
+                                    remaining.push(event);
+                                }
+                            }
+                            None => remaining.push(event),
+                        }
+                    }
+
+                    if !remaining.is_empty() {
+                        let _ = events.send(EventComposite {
+                            suspend_policy: composite.suspend_policy,
+                            events: remaining,
                         });
                     }
-                    break;
+                }
+                Err(e) => {
+                    log::warn!("Could not parse composite event: {:?}", e);
                 }
             }
         }
+
+        // Wake up everyone still waiting for an event. Events read before the connection
+        // was lost have all been sent by now, so `next_event` can stop once it sees this.
+        event_waiters.lock().unwrap().clear();
+        state.send_replace(ConnectionState::Disconnected);
     }
 
-    async fn read_reply_packet(reader: &mut ReadHalf<T>) -> result::Result<ReplyPacket> {
-        // Read header
+    async fn read_packet(reader: &mut ReadHalf<T>) -> result::Result<Packet> {
+        // Replies and commands share the length, id and flags fields
         let mut header_buffer = vec![0u8; ReplyPacketHeader::get_length()];
         reader.read_exact(&mut header_buffer).await?;
 
-        let mut cursor = Cursor::new(&header_buffer);
-        let header =
-            ReplyPacketHeader::read_be(&mut cursor).map_err(|e| result::Error::ParsingError {
-                message: format!("Parsing error: {:?}", e),
-            })?;
-
-        // Read data
-        let data_length = header.length as usize - ReplyPacketHeader::get_length();
+        let length = u32::from_be_bytes(header_buffer[0..4].try_into().unwrap()) as usize;
+        let data_length = length
+            .checked_sub(header_buffer.len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Packet length too small"))?;
         let mut data = vec![0u8; data_length];
         reader.read_exact(&mut data).await?;
 
-        Ok(ReplyPacket { header, data })
+        let flags = header_buffer[8];
+        let mut cursor = Cursor::new(&header_buffer);
+        if flags & REPLY_FLAG != 0 {
+            let header = ReplyPacketHeader::read_be(&mut cursor).map_err(|e| {
+                result::Error::ParsingError {
+                    message: format!("Parsing error: {:?}", e),
+                }
+            })?;
+            Ok(Packet::Reply(ReplyPacket { header, data }))
+        } else {
+            let header = CommandPacketHeader::read_be(&mut cursor).map_err(|e| {
+                result::Error::ParsingError {
+                    message: format!("Parsing error: {:?}", e),
+                }
+            })?;
+            Ok(Packet::Command(CommandPacket { header, data }))
+        }
     }
 
     async fn write_request(
@@ -125,23 +672,26 @@ where
     }
 
     async fn next_packet_id(&self) -> u32 {
-        let mut id = self.packet_id.lock().await;
+        let mut id = self.inner.packet_id.lock().await;
         *id = id.wrapping_add(1);
         *id
     }
 
+    /// Sends a request on the connection `generation` and waits for its reply. Fails with
+    /// `Error::Disconnected` if the client has reconnected since.
     async fn send_request_with_timeout(
         &self,
         command: Command,
         data: Vec<u8>,
         timeout_duration: Duration,
+        generation: u64,
     ) -> result::Result<ReplyPacket> {
         let id = self.next_packet_id().await;
         let (tx, rx) = oneshot::channel();
 
         // Register pending request
         {
-            let mut pending = self.pending_requests.lock().await;
+            let mut pending = self.inner.pending_requests.lock().await;
             pending.insert(id, tx);
         }
 
@@ -153,22 +703,39 @@ where
             command,
         };
 
-        // Send request
+        // Send request, preceded by the releases of objects which are no longer used
         {
-            let mut writer = self.writer.lock().await;
+            let mut writer = self.inner.writer.lock().await;
+            // The writer is only replaced while locked, together with the generation
+            if self.inner.generation.load(Ordering::Acquire) != generation {
+                self.inner.pending_requests.lock().await.remove(&id);
+                return Err(result::Error::Disconnected);
+            }
+            // Releases which cannot be encoded yet stay queued for a later request
+            let releases = self.take_object_releases(false).unwrap_or_default();
+            for (command, data) in releases {
+                // Nobody waits for the reply, so the reader task drops it
+                let header = CommandPacketHeader {
+                    length: CommandPacketHeader::get_length() as u32 + data.len() as u32,
+                    id: self.next_packet_id().await,
+                    flags: 0,
+                    command,
+                };
+                Self::write_request(&mut *writer, &header, &data).await?;
+            }
             Self::write_request(&mut *writer, &header, &data).await?;
         }
 
         // Wait for reply with timeout
         match timeout(timeout_duration, rx).await {
+            Ok(Ok(reply)) if !reply.header.is_success() => Err(result::Error::JdwpError(
+                result::JdwpErrorCode::from(reply.header.error_code),
+            )),
             Ok(Ok(reply)) => Ok(reply),
-            Ok(Err(_)) => Err(result::Error::IoError(io::Error::new(
-                io::ErrorKind::Other,
-                "Reply channel closed",
-            ))),
+            Ok(Err(_)) => Err(self.connection_error()),
             Err(_) => {
                 // Timeout - clean up pending request
-                let mut pending = self.pending_requests.lock().await;
+                let mut pending = self.inner.pending_requests.lock().await;
                 pending.remove(&id);
                 Err(result::Error::IoError(io::Error::new(
                     io::ErrorKind::TimedOut,
@@ -178,45 +745,124 @@ where
         }
     }
 
-    async fn send_bodyless<TReply: for<'a> BinRead<Args<'a> = ()>>(
-        &self,
-        cmd: Command,
-        timeout_duration: Duration,
-    ) -> result::Result<TReply>
-    where
-        for<'a> <TReply as BinRead>::Args<'a>: Default,
-    {
-        let reply_packet = self
-            .send_request_with_timeout(cmd, Vec::new(), timeout_duration)
-            .await?;
+    /// Takes the commands needed to let the VM collect unpinned objects and, once a batch
+    /// is complete or if `all` is set, to dispose of the references given back. Nothing is
+    /// taken if the commands cannot be encoded.
+    fn take_object_releases(&self, all: bool) -> result::Result<Vec<(Command, Vec<u8>)>> {
+        let sizes = *self.inner.sizes.borrow();
+        let mut references = self.inner.object_references.lock().unwrap();
+        let mut releases = Vec::with_capacity(references.unpinned.len() + 1);
+        for object in references.unpinned.iter() {
+            releases.push((
+                object_reference::EnableCollection::COMMAND,
+                object_reference::EnableCollection::encode_request(object, sizes)?,
+            ));
+        }
 
-        let mut cursor = Cursor::new(&reply_packet.data);
-        let reply = TReply::read_be(&mut cursor).map_err(|e| result::Error::ParsingError {
-            message: format!("Binary parsing error: {:?}", e),
-        })?;
+        // Pinned objects must keep their IDs, so their references are kept until unpinned
+        let ObjectReferences { pins, disposed, .. } = &mut *references;
+        let ready: Vec<_> = disposed
+            .iter()
+            .filter(|(object, _)| !pins.contains_key(object))
+            .map(|(object, ref_count)| ObjectReferenceCount {
+                object: *object,
+                ref_count: *ref_count,
+            })
+            .collect();
+        let ref_count: i32 = ready.iter().map(|request| request.ref_count).sum();
+        if !ready.is_empty() && (all || ref_count >= DISPOSE_BATCH_SIZE) {
+            let request = DisposeObjectsRequest { requests: ready };
+            releases.push((
+                virtual_machine::DisposeObjects::COMMAND,
+                virtual_machine::DisposeObjects::encode_request(&request, sizes)?,
+            ));
+            for request in request.requests {
+                disposed.remove(&request.object);
+            }
+        }
+        references.unpinned.clear();
+        Ok(releases)
+    }
 
-        Ok(reply)
+    /// Sends the commands for objects which were unpinned or disposed and waits for the VM
+    /// to process them. Without this they are sent along with later requests.
+    pub async fn flush_object_references(&self) -> result::Result<()> {
+        let generation = self.ensure_connected().await?;
+        for (command, data) in self.take_object_releases(true)? {
+            self.send_request_with_timeout(command, data, Duration::from_secs(5), generation)
+                .await?;
+        }
+        Ok(())
     }
 
-    async fn send_bodyless_variable<TReply: for<'a> BinRead<Args<'a> = JdwpIdSizes>>(
-        &self,
-        cmd: Command,
-        timeout_duration: Duration,
-    ) -> result::Result<TReply> {
-        let reply_packet = self
-            .send_request_with_timeout(cmd, Vec::new(), timeout_duration)
+    /// Keeps `object` from being garbage collected until the returned guard is dropped.
+    pub async fn pin_object(&self, object: ObjectId) -> result::Result<PinnedObject<'_, T>> {
+        let collection_disabled = {
+            let mut references = self.inner.object_references.lock().unwrap();
+            // Collection is still disabled if the object was only just unpinned
+            let was_unpinned = references.unpinned.contains(&object);
+            references.unpinned.retain(|unpinned| *unpinned != object);
+            let pin = references.pins.entry(object).or_insert_with(|| ObjectPin {
+                guards: 0,
+                collection_disabled: Arc::new(OnceCell::new()),
+            });
+            pin.guards += 1;
+            if was_unpinned {
+                let _ = pin.collection_disabled.set(());
+            }
+            pin.collection_disabled.clone()
+        };
+        // From here on the guard undoes the pin if disabling collection fails
+        let pinned = PinnedObject {
+            client: self,
+            object,
+        };
+        collection_disabled
+            .get_or_try_init(|| self.send::<object_reference::DisableCollection>(&object))
             .await?;
+        Ok(pinned)
+    }
 
-        let mut cursor = Cursor::new(&reply_packet.data);
-        let reply = TReply::read_be_args(
-            &mut cursor,
-            self.sizes.ok_or(result::Error::IdSizesUnknown)?,
-        )
-        .map_err(|e| result::Error::ParsingError {
-            message: format!("Binary parsing error: {:?}", e),
-        })?;
+    /// Gives back one reference to `object`, for each time its ID was received from the
+    /// VM and is no longer needed. Once every reference is given back, the VM may reuse
+    /// the ID. References are collected and sent in batches.
+    pub fn dispose_object(&self, object: ObjectId) {
+        *self
+            .inner
+            .object_references
+            .lock()
+            .unwrap()
+            .disposed
+            .entry(object)
+            .or_insert(0) += 1;
+    }
+
+    /// Sends the command `C` and waits for its reply.
+    ///
+    /// If this has to reconnect first, requests containing IDs fail with
+    /// `Error::Disconnected`, as the IDs belong to the lost connection.
+    pub async fn send<C: JdwpCommand>(&self, request: &C::Request) -> result::Result<C::Reply> {
+        let generation = self.inner.generation.load(Ordering::Acquire);
+        let current = self.ensure_connected().await?;
+        // Only bodies with IDs need the sizes
+        if current != generation && C::encode_request(request, None).is_err() {
+            return Err(result::Error::Disconnected);
+        }
+        self.send_on::<C>(request, current).await
+    }
 
-        Ok(reply)
+    /// Sends the command `C` on the connection `generation`, without checking whether
+    /// the client is connected.
+    async fn send_on<C: JdwpCommand>(
+        &self,
+        request: &C::Request,
+        generation: u64,
+    ) -> result::Result<C::Reply> {
+        let data = C::encode_request(request, *self.inner.sizes.borrow())?;
+        let reply = self
+            .send_request_with_timeout(C::COMMAND, data, C::TIMEOUT, generation)
+            .await?;
+        C::decode_reply(&reply.data, *self.inner.sizes.borrow())
     }
 
     async fn do_handshake(stream: &mut T) -> result::Result<()> {
@@ -245,48 +891,2007 @@ where
     }
 
     pub async fn vm_get_version(&self) -> result::Result<VersionReply> {
-        self.send_bodyless(Command::VirtualMachineVersion, Duration::from_secs(5))
-            .await
+        self.send::<virtual_machine::Version>(&()).await
     }
 
     pub async fn vm_get_all_classes(&self) -> result::Result<AllClassesReply> {
-        self.send_bodyless_variable(Command::VirtualMachineAllClasses, Duration::from_secs(5))
-            .await
+        self.send::<virtual_machine::AllClasses>(&()).await
+    }
+
+    pub async fn vm_get_classes_by_signature(
+        &self,
+        signature: &str,
+    ) -> result::Result<ClassesBySignatureReply> {
+        self.send::<virtual_machine::ClassesBySignature>(&ClassesBySignatureRequest {
+            signature: JdwpString {
+                string: signature.to_string(),
+            },
+        })
+        .await
+    }
+
+    pub async fn vm_get_capabilities_new(&self) -> result::Result<Capabilities> {
+        self.send::<virtual_machine::CapabilitiesNew>(&()).await
+    }
+
+    pub async fn vm_get_capabilities(&self) -> result::Result<CapabilitiesReply> {
+        self.send::<virtual_machine::Capabilities>(&()).await
+    }
+
+    /// Fails with `Error::CapabilityMissing` unless the VM has the capability checked
+    /// by `has_capability`. `name` is the capability's name in the JDWP specification.
+    fn require_capability(
+        &self,
+        name: &'static str,
+        has_capability: fn(&Capabilities) -> bool,
+    ) -> result::Result<()> {
+        if !has_capability(&self.session().capabilities) {
+            return Err(result::Error::CapabilityMissing(name));
+        }
+        Ok(())
+    }
+
+    /// Replaces the definitions of loaded classes. Fails with `Error::CapabilityMissing`
+    /// if the VM cannot redefine classes.
+    pub async fn redefine_classes(
+        &self,
+        classes: Vec<(ReferenceTypeId, Vec<u8>)>,
+    ) -> result::Result<()> {
+        self.require_capability("canRedefineClasses", |c| c.can_redefine_classes)?;
+
+        self.send::<virtual_machine::RedefineClasses>(&RedefineClassesRequest {
+            classes: classes
+                .into_iter()
+                .map(|(ref_type, class_bytes)| ClassDefinition {
+                    ref_type,
+                    class_bytes,
+                })
+                .collect(),
+        })
+        .await
+    }
+
+    /// Hot-swaps the class file `class_bytes` into every class loader which loaded a class
+    /// of the same name. The name is read from the class file.
+    pub async fn redefine_class(&self, class_bytes: Vec<u8>) -> result::Result<()> {
+        let class_file = JavaClassFile::read(&mut Cursor::new(&class_bytes)).map_err(|e| {
+            result::Error::ParsingError {
+                message: format!("Invalid class file: {}", e),
+            }
+        })?;
+        let name = class_file
+            .find_name()
+            .ok_or_else(|| result::Error::ParsingError {
+                message: "Invalid class file: this_class is not a class entry".to_string(),
+            })?;
+        let signature = format!("L{};", name);
+        let loaded = self.vm_get_classes_by_signature(&signature).await?;
+        if loaded.classes.is_empty() {
+            return Err(result::Error::ClassNotLoaded { signature });
+        }
+
+        let classes = loaded
+            .classes
+            .into_iter()
+            .map(|class| (class.type_id, class_bytes.clone()))
+            .collect();
+        self.redefine_classes(classes).await
     }
 
     pub async fn vm_get_id_sizes(&self) -> result::Result<IdSizesReply> {
-        self.send_bodyless(Command::VirtualMachineIDSizes, Duration::from_secs(5))
-            .await
-    }
-    pub async fn get_id_sizes(&mut self) -> result::Result<()> {
-        let sizes = self.vm_get_id_sizes().await?;
-        let field_id: u8 = sizes
-            .field_id_size
-            .try_into()
-            .map_err(|_| result::Error::IdSizesTruncated)?;
-        let method_id: u8 = sizes
-            .method_id_size
-            .try_into()
-            .map_err(|_| result::Error::IdSizesTruncated)?;
-        let object_id: u8 = sizes
-            .object_id_size
-            .try_into()
-            .map_err(|_| result::Error::IdSizesTruncated)?;
-        let ref_id: u8 = sizes
-            .reference_type_id_size
-            .try_into()
-            .map_err(|_| result::Error::IdSizesTruncated)?;
-        let frame_id: u8 = sizes
-            .frame_id_size
-            .try_into()
-            .map_err(|_| result::Error::IdSizesTruncated)?;
-        self.sizes = Some(JdwpIdSizes {
-            field_id_size: field_id,
-            method_id_size: method_id,
-            object_id_size: object_id,
-            reference_type_id_size: ref_id,
-            frame_id_size: frame_id,
-        });
+        self.send::<virtual_machine::IdSizes>(&()).await
+    }
+
+    pub async fn event_request_set(&self, request: &EventRequestSet) -> result::Result<RequestId> {
+        self.send::<event_request::Set>(request).await
+    }
+
+    pub async fn event_request_clear(
+        &self,
+        event_kind: EventKind,
+        request_id: RequestId,
+    ) -> result::Result<()> {
+        self.send::<event_request::Clear>(&EventRequestClear {
+            event_kind,
+            request_id,
+        })
+        .await
+    }
+
+    pub async fn event_request_clear_all_breakpoints(&self) -> result::Result<()> {
+        self.send::<event_request::ClearAllBreakpoints>(&()).await
+    }
+
+    /// Sets a breakpoint at `location`. Breakpoint events caused by it carry the returned ID.
+    pub async fn set_breakpoint(
+        &self,
+        location: Location,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<RequestId> {
+        self.event_request_set(&EventRequestSet {
+            event_kind: EventKind::Breakpoint,
+            suspend_policy,
+            modifiers: vec![EventModifier::LocationOnly(location)],
+        })
+        .await
+    }
+
+    pub async fn clear_breakpoint(&self, request_id: RequestId) -> result::Result<()> {
+        self.event_request_clear(EventKind::Breakpoint, request_id)
+            .await
+    }
+
+    /// Sets a breakpoint on a source line of the class with the binary name `class_name`
+    /// (e.g. `com.example.Foo`). If the class has not been loaded yet, the breakpoint is
+    /// deferred until the VM prepares it.
+    pub async fn set_line_breakpoint(
+        &self,
+        class_name: &str,
+        line_number: i32,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<LineBreakpoint> {
+        // Requested before looking for loaded classes, so that a class prepared in between
+        // is not missed. Keeps the thread which loads the class suspended until the
+        // breakpoint is in place.
+        let class_prepare = self
+            .event_request_set(&EventRequestSet {
+                event_kind: EventKind::ClassPrepare,
+                suspend_policy: SuspendPolicy::EventThread,
+                modifiers: vec![EventModifier::ClassMatch(class_name.to_string())],
+            })
+            .await?;
+        self.inner.deferred_breakpoints.lock().await.insert(
+            class_prepare,
+            DeferredBreakpoint {
+                line_number,
+                suspend_policy,
+                classes: HashSet::new(),
+                installed: Vec::new(),
+            },
+        );
+        let deferred = LineBreakpoint::Deferred(class_prepare);
+
+        let signature = format!("L{};", class_name.replace('.', "/"));
+        let installed = async {
+            let loaded = self.vm_get_classes_by_signature(&signature).await?;
+            for class in loaded.classes {
+                self.install_deferred_breakpoint(class_prepare, class.ref_type_tag, class.type_id)
+                    .await?;
+            }
+            // Includes breakpoints installed meanwhile for a ClassPrepare event
+            Ok(self
+                .inner
+                .deferred_breakpoints
+                .lock()
+                .await
+                .get(&class_prepare)
+                .map(|deferred| deferred.installed.clone())
+                .unwrap_or_default())
+        }
+        .await;
+
+        match installed {
+            Ok(installed) if installed.is_empty() => Ok(deferred),
+            Ok(installed) => Ok(LineBreakpoint::Installed {
+                class_prepare,
+                breakpoints: installed,
+            }),
+            Err(e) => {
+                let _ = self.clear_line_breakpoint(&deferred).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Clears a breakpoint set with `set_line_breakpoint`, including the breakpoints
+    /// installed for classes prepared since.
+    pub async fn clear_line_breakpoint(&self, breakpoint: &LineBreakpoint) -> result::Result<()> {
+        let (LineBreakpoint::Installed { class_prepare, .. }
+        | LineBreakpoint::Deferred(class_prepare)) = breakpoint;
+        self.event_request_clear(EventKind::ClassPrepare, *class_prepare)
+            .await?;
+        let installed = self
+            .inner
+            .deferred_breakpoints
+            .lock()
+            .await
+            .remove(class_prepare)
+            .map(|deferred| deferred.installed)
+            .unwrap_or_default();
+
+        for request_id in installed {
+            self.clear_breakpoint(request_id).await?;
+        }
         Ok(())
     }
+
+    async fn install_line_breakpoint(
+        &self,
+        type_tag: TypeTag,
+        ref_type: ReferenceTypeId,
+        line_number: i32,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<Vec<RequestId>> {
+        let mut installed = Vec::new();
+        for location in self
+            .find_line_locations(type_tag, ref_type, line_number)
+            .await?
+        {
+            installed.push(self.set_breakpoint(location, suspend_policy).await?);
+        }
+        Ok(installed)
+    }
+
+    /// Installs the line breakpoint of the ClassPrepare request `class_prepare` in
+    /// `ref_type`. Does nothing if that was done before, as a class loaded while the
+    /// breakpoint is set is both found loaded and reported as prepared.
+    async fn install_deferred_breakpoint(
+        &self,
+        class_prepare: RequestId,
+        type_tag: TypeTag,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<Vec<RequestId>> {
+        let (line_number, suspend_policy) = {
+            let mut deferred_breakpoints = self.inner.deferred_breakpoints.lock().await;
+            let Some(deferred) = deferred_breakpoints.get_mut(&class_prepare) else {
+                return Ok(Vec::new());
+            };
+            if !deferred.classes.insert(ref_type) {
+                return Ok(Vec::new());
+            }
+            (deferred.line_number, deferred.suspend_policy)
+        };
+
+        let installed = self
+            .install_line_breakpoint(type_tag, ref_type, line_number, suspend_policy)
+            .await?;
+        // The breakpoint may have been cleared in the meantime
+        match self
+            .inner
+            .deferred_breakpoints
+            .lock()
+            .await
+            .get_mut(&class_prepare)
+        {
+            Some(deferred) => deferred.installed.extend(installed.iter().copied()),
+            None => {
+                for request_id in installed {
+                    let _ = self.clear_breakpoint(request_id).await;
+                }
+                return Ok(Vec::new());
+            }
+        }
+        Ok(installed)
+    }
+
+    /// Takes the ClassPrepare events of deferred line breakpoints out of `composite`, and
+    /// installs their breakpoints on a separate task. The threads loading the classes
+    /// were only suspended for this, so they are resumed once that is done.
+    async fn take_deferred_class_prepares(self, composite: EventComposite) -> EventComposite {
+        let mut prepared = Vec::new();
+        let mut threads = Vec::new();
+        let mut remaining = Vec::with_capacity(composite.events.len());
+        {
+            let deferred_breakpoints = self.inner.deferred_breakpoints.lock().await;
+            for event in composite.events {
+                match event {
+                    Event::ClassPrepare {
+                        request_id,
+                        thread,
+                        ref_type_tag,
+                        type_id,
+                        ..
+                    } if deferred_breakpoints.contains_key(&request_id) => {
+                        prepared.push((request_id, ref_type_tag, type_id));
+                        if !threads.contains(&thread) {
+                            threads.push(thread);
+                        }
+                    }
+                    event => remaining.push(event),
+                }
+            }
+        }
+
+        // With `SuspendPolicy::All`, one of the remaining events suspended the whole VM,
+        // and resuming it is up to whoever handles that event
+        if composite.suspend_policy == SuspendPolicy::All {
+            threads.clear();
+        }
+        if !prepared.is_empty() {
+            tokio::spawn(async move {
+                for (class_prepare, type_tag, ref_type) in prepared {
+                    if let Err(e) = self
+                        .install_deferred_breakpoint(class_prepare, type_tag, ref_type)
+                        .await
+                    {
+                        log::error!("Could not install deferred breakpoint: {}", e);
+                    }
+                }
+                for thread in threads {
+                    if let Err(e) = self.thread_resume(thread).await {
+                        log::error!("Could not resume thread after class prepare: {}", e);
+                    }
+                }
+            });
+        }
+
+        EventComposite {
+            suspend_policy: composite.suspend_policy,
+            events: remaining,
+        }
+    }
+
+    /// Requests exception events for `exception` (or any throwable if `None`) thrown in
+    /// classes matching `class_match` but none of `class_exclude`. At least one of `caught`
+    /// and `uncaught` should be set, otherwise no events are reported.
+    pub async fn set_exception_breakpoint(
+        &self,
+        exception: Option<ReferenceTypeId>,
+        caught: bool,
+        uncaught: bool,
+        class_match: &[&str],
+        class_exclude: &[&str],
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<RequestId> {
+        let mut modifiers = vec![EventModifier::ExceptionOnly {
+            exception,
+            caught,
+            uncaught,
+        }];
+        modifiers.extend(
+            class_match
+                .iter()
+                .map(|pattern| EventModifier::ClassMatch(pattern.to_string())),
+        );
+        modifiers.extend(
+            class_exclude
+                .iter()
+                .map(|pattern| EventModifier::ClassExclude(pattern.to_string())),
+        );
+
+        self.event_request_set(&EventRequestSet {
+            event_kind: EventKind::Exception,
+            suspend_policy,
+            modifiers,
+        })
+        .await
+    }
+
+    pub async fn clear_exception_breakpoint(&self, request_id: RequestId) -> result::Result<()> {
+        self.event_request_clear(EventKind::Exception, request_id)
+            .await
+    }
+
+    /// Requests FieldAccess events for the field `field_name` of `ref_type`, optionally
+    /// limited to accesses through `instance`.
+    pub async fn set_field_access_watchpoint(
+        &self,
+        ref_type: ReferenceTypeId,
+        field_name: &str,
+        instance: Option<ObjectId>,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<RequestId> {
+        self.require_capability("canWatchFieldAccess", |c| c.can_watch_field_access)?;
+        self.set_watchpoint(
+            EventKind::FieldAccess,
+            ref_type,
+            field_name,
+            instance,
+            suspend_policy,
+        )
+        .await
+    }
+
+    /// Requests FieldModification events for the field `field_name` of `ref_type`,
+    /// optionally limited to modifications through `instance`.
+    pub async fn set_field_modification_watchpoint(
+        &self,
+        ref_type: ReferenceTypeId,
+        field_name: &str,
+        instance: Option<ObjectId>,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<RequestId> {
+        self.require_capability("canWatchFieldModification", |c| {
+            c.can_watch_field_modification
+        })?;
+        self.set_watchpoint(
+            EventKind::FieldModification,
+            ref_type,
+            field_name,
+            instance,
+            suspend_policy,
+        )
+        .await
+    }
+
+    async fn set_watchpoint(
+        &self,
+        event_kind: EventKind,
+        ref_type: ReferenceTypeId,
+        field_name: &str,
+        instance: Option<ObjectId>,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<RequestId> {
+        if instance.is_some() {
+            self.require_capability("canUseInstanceFilters", |c| c.can_use_instance_filters)?;
+        }
+        let field = self.find_field(ref_type, field_name).await?;
+        let mut modifiers = vec![EventModifier::FieldOnly {
+            ref_type,
+            field: field.field_id,
+        }];
+        if let Some(instance) = instance {
+            modifiers.push(EventModifier::InstanceOnly(instance));
+        }
+
+        self.event_request_set(&EventRequestSet {
+            event_kind,
+            suspend_policy,
+            modifiers,
+        })
+        .await
+    }
+
+    /// Reads the current value of the field reported by a FieldAccess or FieldModification
+    /// event. The VM does not send it with the event, but while the event thread is
+    /// suspended this is the value before the modification. Returns `None` for other events.
+    pub async fn get_watched_field_value(&self, event: &Event) -> result::Result<Option<Value>> {
+        let (type_id, field_id, object) = match event {
+            Event::FieldAccess {
+                type_id,
+                field_id,
+                object,
+                ..
+            }
+            | Event::FieldModification {
+                type_id,
+                field_id,
+                object,
+                ..
+            } => (*type_id, *field_id, object),
+            _ => return Ok(None),
+        };
+
+        let fields = vec![field_id];
+        let values = if object.object_id.is_null() {
+            self.reference_type_get_values(type_id, fields).await?
+        } else {
+            self.object_get_values(object.object_id, fields).await?
+        };
+        Ok(values.into_iter().next())
+    }
+
+    pub async fn reference_type_get_signature(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<JdwpString> {
+        self.send::<reference_type::Signature>(&ref_type).await
+    }
+
+    /// Returns the class loader of the type. The bootstrap class loader has the ID 0.
+    pub async fn reference_type_get_class_loader(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<ClassLoaderId> {
+        self.send::<reference_type::ClassLoader>(&ref_type).await
+    }
+
+    pub async fn reference_type_get_modifiers(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<ClassAccessFlags> {
+        let mod_bits: i32 = self.send::<reference_type::Modifiers>(&ref_type).await?;
+        Ok(ClassAccessFlags::from_bits_retain(mod_bits as u16))
+    }
+
+    pub async fn reference_type_get_fields(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<FieldsReply> {
+        self.send::<reference_type::Fields>(&ref_type).await
+    }
+
+    /// Looks up a field declared by `ref_type` by name. Inherited fields are not searched.
+    pub async fn find_field(
+        &self,
+        ref_type: ReferenceTypeId,
+        name: &str,
+    ) -> result::Result<ReferenceTypeField> {
+        self.reference_type_get_fields(ref_type)
+            .await?
+            .fields
+            .into_iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| result::Error::FieldNotFound {
+                name: name.to_string(),
+            })
+    }
+
+    /// Reads static fields of `ref_type`.
+    pub async fn reference_type_get_values(
+        &self,
+        ref_type: ReferenceTypeId,
+        fields: Vec<FieldId>,
+    ) -> result::Result<Vec<Value>> {
+        let reply: ValuesReply = self
+            .send::<reference_type::GetValues>(&ReferenceTypeGetValuesRequest { ref_type, fields })
+            .await?;
+        Ok(reply.values)
+    }
+
+    pub async fn reference_type_get_fields_with_generic(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<FieldsWithGenericReply> {
+        self.send::<reference_type::FieldsWithGeneric>(&ref_type)
+            .await
+    }
+
+    pub async fn reference_type_get_methods(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<MethodsReply> {
+        self.send::<reference_type::Methods>(&ref_type).await
+    }
+
+    pub async fn reference_type_get_methods_with_generic(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<MethodsWithGenericReply> {
+        self.send::<reference_type::MethodsWithGeneric>(&ref_type)
+            .await
+    }
+
+    pub async fn reference_type_get_source_file(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<JdwpString> {
+        self.send::<reference_type::SourceFile>(&ref_type).await
+    }
+
+    pub async fn reference_type_get_status(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<ClassStatus> {
+        self.send::<reference_type::Status>(&ref_type).await
+    }
+
+    pub async fn reference_type_get_interfaces(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<InterfacesReply> {
+        self.send::<reference_type::Interfaces>(&ref_type).await
+    }
+
+    pub async fn reference_type_get_class_object(
+        &self,
+        ref_type: ReferenceTypeId,
+    ) -> result::Result<ClassObjectId> {
+        self.send::<reference_type::ClassObject>(&ref_type).await
+    }
+
+    pub async fn class_type_invoke_method(
+        &self,
+        class: ReferenceTypeId,
+        thread: ThreadId,
+        method_id: MethodId,
+        arguments: Vec<Value>,
+        options: InvokeOptions,
+    ) -> result::Result<InvokeMethodReply> {
+        self.send::<class_type::InvokeMethod>(&StaticInvokeRequest {
+            class,
+            thread,
+            method_id,
+            arguments,
+            options,
+        })
+        .await
+    }
+
+    /// Creates a new instance of `class` using the constructor `method_id`.
+    pub async fn class_type_new_instance(
+        &self,
+        class: ReferenceTypeId,
+        thread: ThreadId,
+        method_id: MethodId,
+        arguments: Vec<Value>,
+        options: InvokeOptions,
+    ) -> result::Result<NewInstanceReply> {
+        self.send::<class_type::NewInstance>(&StaticInvokeRequest {
+            class,
+            thread,
+            method_id,
+            arguments,
+            options,
+        })
+        .await
+    }
+
+    pub async fn interface_type_invoke_method(
+        &self,
+        interface: ReferenceTypeId,
+        thread: ThreadId,
+        method_id: MethodId,
+        arguments: Vec<Value>,
+        options: InvokeOptions,
+    ) -> result::Result<InvokeMethodReply> {
+        self.send::<interface_type::InvokeMethod>(&StaticInvokeRequest {
+            class: interface,
+            thread,
+            method_id,
+            arguments,
+            options,
+        })
+        .await
+    }
+
+    pub async fn method_get_line_table(
+        &self,
+        ref_type: ReferenceTypeId,
+        method_id: MethodId,
+    ) -> result::Result<LineTableReply> {
+        self.send::<method::LineTable>(&MethodRequest {
+            ref_type,
+            method_id,
+        })
+        .await
+    }
+
+    pub async fn method_get_variable_table(
+        &self,
+        ref_type: ReferenceTypeId,
+        method_id: MethodId,
+    ) -> result::Result<VariableTableReply> {
+        self.send::<method::VariableTable>(&MethodRequest {
+            ref_type,
+            method_id,
+        })
+        .await
+    }
+
+    pub async fn method_get_variable_table_with_generic(
+        &self,
+        ref_type: ReferenceTypeId,
+        method_id: MethodId,
+    ) -> result::Result<VariableTableReply> {
+        let reply: VariableTableWithGenericReply = self
+            .send::<method::VariableTableWithGeneric>(&MethodRequest {
+                ref_type,
+                method_id,
+            })
+            .await?;
+        Ok(reply.0)
+    }
+
+    pub async fn method_get_bytecodes(
+        &self,
+        ref_type: ReferenceTypeId,
+        method_id: MethodId,
+    ) -> result::Result<BytecodesReply> {
+        self.require_capability("canGetBytecodes", |c| c.can_get_bytecodes)?;
+        self.send::<method::Bytecodes>(&MethodRequest {
+            ref_type,
+            method_id,
+        })
+        .await
+    }
+
+    /// Resolves a source line of a loaded type to executable locations, one per method
+    /// containing code for that line.
+    pub async fn find_line_locations(
+        &self,
+        type_tag: TypeTag,
+        ref_type: ReferenceTypeId,
+        line_number: i32,
+    ) -> result::Result<Vec<Location>> {
+        let methods = self.reference_type_get_methods(ref_type).await?;
+        let mut locations = Vec::new();
+        for method in methods.methods {
+            let line_table = match self.method_get_line_table(ref_type, method.method_id).await {
+                Ok(line_table) => line_table,
+                // Abstract and native methods, or classes compiled without -g
+                Err(result::Error::JdwpError(
+                    result::JdwpErrorCode::AbsentInformation | result::JdwpErrorCode::NativeMethod,
+                )) => continue,
+                Err(e) => return Err(e),
+            };
+
+            if let Some(index) = line_table
+                .code_indices_for_line(line_number)
+                .into_iter()
+                .min()
+            {
+                locations.push(Location {
+                    type_tag,
+                    class_id: ref_type,
+                    method_id: method.method_id,
+                    index,
+                });
+            }
+        }
+
+        Ok(locations)
+    }
+
+    pub async fn object_get_reference_type(
+        &self,
+        object: ObjectId,
+    ) -> result::Result<ObjectReferenceTypeReply> {
+        self.send::<object_reference::ReferenceType>(&object).await
+    }
+
+    pub async fn object_get_values(
+        &self,
+        object: ObjectId,
+        fields: Vec<FieldId>,
+    ) -> result::Result<Vec<Value>> {
+        let reply: ValuesReply = self
+            .send::<object_reference::GetValues>(&ObjectGetValuesRequest { object, fields })
+            .await?;
+        Ok(reply.values)
+    }
+
+    pub async fn object_set_values(
+        &self,
+        object: ObjectId,
+        values: Vec<FieldValue>,
+    ) -> result::Result<()> {
+        self.send::<object_reference::SetValues>(&ObjectSetValuesRequest { object, values })
+            .await
+    }
+
+    pub async fn object_get_monitor_info(
+        &self,
+        object: ObjectId,
+    ) -> result::Result<MonitorInfoReply> {
+        self.require_capability("canGetMonitorInfo", |c| c.can_get_monitor_info)?;
+        self.send::<object_reference::MonitorInfo>(&object).await
+    }
+
+    /// Invokes an instance method on `object` in the suspended `thread`. `class` is the
+    /// type declaring the method, which matters for `InvokeOptions::NONVIRTUAL`.
+    pub async fn object_invoke_method(
+        &self,
+        object: ObjectId,
+        thread: ThreadId,
+        class: ReferenceTypeId,
+        method_id: MethodId,
+        arguments: Vec<Value>,
+        options: InvokeOptions,
+    ) -> result::Result<InvokeMethodReply> {
+        self.send::<object_reference::InvokeMethod>(&ObjectInvokeRequest {
+            object,
+            thread,
+            class,
+            method_id,
+            arguments,
+            options,
+        })
+        .await
+    }
+
+    pub async fn string_get_value(&self, string: ObjectId) -> result::Result<JdwpString> {
+        self.send::<string_reference::Value>(&string).await
+    }
+
+    pub async fn array_get_length(&self, array: ObjectId) -> result::Result<i32> {
+        self.send::<array_reference::Length>(&array).await
+    }
+
+    pub async fn array_get_values(
+        &self,
+        array: ObjectId,
+        first_index: i32,
+        length: i32,
+    ) -> result::Result<ArrayRegion> {
+        self.send::<array_reference::GetValues>(&ArrayGetValuesRequest {
+            array,
+            first_index,
+            length,
+        })
+        .await
+    }
+
+    /// Replaces array components starting at `first_index`. Values are written untagged,
+    /// so they must match the component type exactly.
+    pub async fn array_set_values(
+        &self,
+        array: ObjectId,
+        first_index: i32,
+        values: Vec<Value>,
+    ) -> result::Result<()> {
+        self.send::<array_reference::SetValues>(&ArraySetValuesRequest {
+            array,
+            first_index,
+            values,
+        })
+        .await
+    }
+
+    pub async fn vm_get_all_threads(&self) -> result::Result<AllThreadsReply> {
+        self.send::<virtual_machine::AllThreads>(&()).await
+    }
+
+    pub async fn vm_get_top_level_thread_groups(
+        &self,
+    ) -> result::Result<TopLevelThreadGroupsReply> {
+        self.send::<virtual_machine::TopLevelThreadGroups>(&())
+            .await
+    }
+
+    /// Suspends every thread of the VM. Each call has to be undone by a `vm_resume`.
+    pub async fn vm_suspend(&self) -> result::Result<()> {
+        self.send::<virtual_machine::Suspend>(&()).await
+    }
+
+    pub async fn vm_resume(&self) -> result::Result<()> {
+        self.send::<virtual_machine::Resume>(&()).await
+    }
+
+    /// Terminates the VM with `exit_code`.
+    pub async fn vm_exit(&self, exit_code: i32) -> result::Result<()> {
+        self.send::<virtual_machine::Exit>(&exit_code).await
+    }
+
+    /// Detaches from the VM, which clears all event requests, resumes the threads
+    /// suspended by the debugger and keeps the VM running. The client cannot be used
+    /// afterwards. See also `close`.
+    pub async fn vm_dispose(&self) -> result::Result<()> {
+        self.send::<virtual_machine::Dispose>(&()).await?;
+        self.inner.disposed.store(true, Ordering::Relaxed);
+        // Also ends `next_event`, as no more events will come
+        self.inner.state.send_replace(ConnectionState::Disconnected);
+        Ok(())
+    }
+
+    /// Detaches from the VM with `vm_dispose` if it is still connected, and closes the
+    /// connection. Prefer this over dropping the client, which only tries to dispose.
+    pub async fn close(self) -> result::Result<()> {
+        if !self.inner.disposed.load(Ordering::Relaxed)
+            && *self.inner.state.borrow() == ConnectionState::Connected
+        {
+            self.vm_dispose().await?;
+        }
+        Ok(())
+    }
+
+    /// Makes the VM hold back events until `vm_release_events`, e.g. while the debugger
+    /// inspects a consistent state.
+    pub async fn vm_hold_events(&self) -> result::Result<()> {
+        self.send::<virtual_machine::HoldEvents>(&()).await
+    }
+
+    pub async fn vm_release_events(&self) -> result::Result<()> {
+        self.send::<virtual_machine::ReleaseEvents>(&()).await
+    }
+
+    pub async fn thread_get_name(&self, thread: ThreadId) -> result::Result<JdwpString> {
+        self.send::<thread_reference::Name>(&thread).await
+    }
+
+    pub async fn thread_suspend(&self, thread: ThreadId) -> result::Result<()> {
+        self.send::<thread_reference::Suspend>(&thread).await
+    }
+
+    pub async fn thread_resume(&self, thread: ThreadId) -> result::Result<()> {
+        self.send::<thread_reference::Resume>(&thread).await
+    }
+
+    pub async fn thread_get_status(&self, thread: ThreadId) -> result::Result<ThreadStatusReply> {
+        self.send::<thread_reference::Status>(&thread).await
+    }
+
+    pub async fn thread_get_thread_group(&self, thread: ThreadId) -> result::Result<ThreadGroupId> {
+        self.send::<thread_reference::ThreadGroup>(&thread).await
+    }
+
+    /// Returns `length` frames of a suspended thread starting at `start_frame`, where 0 is
+    /// the current frame. A `length` of -1 returns all remaining frames.
+    pub async fn thread_get_frames(
+        &self,
+        thread: ThreadId,
+        start_frame: i32,
+        length: i32,
+    ) -> result::Result<ThreadFramesReply> {
+        self.send::<thread_reference::Frames>(&ThreadFramesRequest {
+            thread,
+            start_frame,
+            length,
+        })
+        .await
+    }
+
+    pub async fn thread_get_frame_count(&self, thread: ThreadId) -> result::Result<i32> {
+        self.send::<thread_reference::FrameCount>(&thread).await
+    }
+
+    pub async fn thread_get_suspend_count(&self, thread: ThreadId) -> result::Result<i32> {
+        self.send::<thread_reference::SuspendCount>(&thread).await
+    }
+
+    /// Lists the monitors owned by a suspended thread.
+    pub async fn thread_get_owned_monitors(
+        &self,
+        thread: ThreadId,
+    ) -> result::Result<OwnedMonitorsReply> {
+        self.require_capability("canGetOwnedMonitorInfo", |c| c.can_get_owned_monitor_info)?;
+        self.send::<thread_reference::OwnedMonitors>(&thread).await
+    }
+
+    /// Returns the monitor a suspended thread is waiting to enter, or waiting on in
+    /// `Object.wait`.
+    pub async fn thread_get_current_contended_monitor(
+        &self,
+        thread: ThreadId,
+    ) -> result::Result<Option<TaggedObjectId>> {
+        self.require_capability("canGetCurrentContendedMonitor", |c| {
+            c.can_get_current_contended_monitor
+        })?;
+        let monitor: TaggedObjectId = self
+            .send::<thread_reference::CurrentContendedMonitor>(&thread)
+            .await?;
+        if monitor.object_id.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(monitor))
+    }
+
+    /// Returns from the current method of a suspended thread with `value` once the thread
+    /// is resumed, without executing the rest of the method.
+    pub async fn thread_force_early_return(
+        &self,
+        thread: ThreadId,
+        value: Value,
+    ) -> result::Result<()> {
+        self.require_capability("canForceEarlyReturn", |c| c.can_force_early_return)?;
+        self.send::<thread_reference::ForceEarlyReturn>(&ForceEarlyReturnRequest { thread, value })
+            .await
+    }
+
+    pub async fn stack_frame_get_values(
+        &self,
+        thread: ThreadId,
+        frame: FrameId,
+        slots: Vec<FrameSlot>,
+    ) -> result::Result<Vec<Value>> {
+        let reply: ValuesReply = self
+            .send::<stack_frame::GetValues>(&StackFrameGetValuesRequest {
+                thread,
+                frame,
+                slots,
+            })
+            .await?;
+        Ok(reply.values)
+    }
+
+    pub async fn stack_frame_set_values(
+        &self,
+        thread: ThreadId,
+        frame: FrameId,
+        slot_values: Vec<SlotValue>,
+    ) -> result::Result<()> {
+        self.send::<stack_frame::SetValues>(&StackFrameSetValuesRequest {
+            thread,
+            frame,
+            slot_values,
+        })
+        .await
+    }
+
+    /// Returns the `this` object of the frame, or `None` for static and native methods.
+    pub async fn stack_frame_get_this_object(
+        &self,
+        thread: ThreadId,
+        frame: FrameId,
+    ) -> result::Result<Option<TaggedObjectId>> {
+        let this_object: TaggedObjectId = self
+            .send::<stack_frame::ThisObject>(&StackFrameRequest { thread, frame })
+            .await?;
+        if this_object.object_id.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(this_object))
+    }
+
+    /// Pops `frame` and all frames above it off the stack of a suspended thread. Once resumed,
+    /// the thread re-executes the invoke instruction of the caller.
+    pub async fn stack_frame_pop_frames(
+        &self,
+        thread: ThreadId,
+        frame: FrameId,
+    ) -> result::Result<()> {
+        self.require_capability("canPopFrames", |c| c.can_pop_frames)?;
+        self.send::<stack_frame::PopFrames>(&StackFrameRequest { thread, frame })
+            .await
+    }
+
+    /// Lists all live threads together with their names and states.
+    pub async fn list_threads(&self) -> result::Result<Vec<ThreadInfo>> {
+        let all_threads = self.vm_get_all_threads().await?;
+        let mut threads = Vec::with_capacity(all_threads.threads.len());
+        for thread in all_threads.threads {
+            let name = self.thread_get_name(thread).await?;
+            let status = self.thread_get_status(thread).await?;
+            threads.push(ThreadInfo {
+                thread,
+                name: name.string,
+                status: status.thread_status,
+                suspend_status: status.suspend_status,
+            });
+        }
+
+        Ok(threads)
+    }
+
+    /// Requests a single step of `thread`. The request is reported once and should be
+    /// cleared after its SingleStep event arrives.
+    pub async fn step_request_set(
+        &self,
+        thread: ThreadId,
+        size: StepSize,
+        depth: StepDepth,
+        suspend_policy: SuspendPolicy,
+    ) -> result::Result<RequestId> {
+        self.event_request_set(&EventRequestSet {
+            event_kind: EventKind::SingleStep,
+            suspend_policy,
+            modifiers: vec![
+                EventModifier::Step {
+                    thread,
+                    size,
+                    depth,
+                },
+                EventModifier::Count(1),
+            ],
+        })
+        .await
+    }
+
+    /// Steps the suspended `thread` and waits until it stops again, for at most a minute.
+    /// Returns the location the thread stopped at.
+    ///
+    /// If the returned future is dropped before the thread stops, the step request stays
+    /// set and its SingleStep event is reported by `next_event`.
+    pub async fn step(
+        &self,
+        thread: ThreadId,
+        size: StepSize,
+        depth: StepDepth,
+    ) -> result::Result<Location> {
+        let request_id = self
+            .step_request_set(thread, size, depth, SuspendPolicy::EventThread)
+            .await?;
+        let mut waiter = self.wait_for_event(request_id);
+
+        let stepped = match self.thread_resume(thread).await {
+            Ok(()) => match timeout(STEP_TIMEOUT, &mut waiter.receiver).await {
+                Ok(Ok(event)) => Ok(event),
+                Ok(Err(_)) => Err(self.connection_error()),
+                Err(_) => Err(result::Error::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Step timed out",
+                ))),
+            },
+            Err(e) => Err(e),
+        };
+        drop(waiter);
+
+        self.event_request_clear(EventKind::SingleStep, request_id)
+            .await?;
+        match stepped? {
+            Event::SingleStep { location, .. } => Ok(location),
+            other => Err(result::Error::ParsingError {
+                message: format!("Unexpected event for a step request: {:?}", other),
+            }),
+        }
+    }
+
+    /// Registers interest in the next event caused by `request_id`. The event will not be
+    /// reported by `next_event`.
+    fn wait_for_event(&self, request_id: RequestId) -> EventWaiter<'_> {
+        let (tx, rx) = oneshot::channel();
+        self.inner
+            .event_waiters
+            .lock()
+            .unwrap()
+            .insert(request_id, tx);
+        EventWaiter {
+            waiters: &self.inner.event_waiters,
+            request_id,
+            receiver: rx,
+        }
+    }
+
+    /// Waits for the next composite event sent by the VM.
+    /// Returns `None` once the connection has been closed and all events received before
+    /// have been returned.
+    ///
+    /// ClassPrepare events of deferred line breakpoints are handled in the background and
+    /// not reported.
+    pub async fn next_event(&self) -> Option<EventComposite> {
+        let mut events = self.inner.events.lock().await;
+        let mut state = self.inner.state.subscribe();
+        tokio::select! {
+            biased;
+            composite = events.recv() => composite,
+            _ = state.wait_for(|state| *state == ConnectionState::Disconnected) => {
+                events.try_recv().ok()
+            }
+        }
+    }
+}
+
+/// Runs once the client is dropped and no deferred line breakpoint is being installed,
+/// see `JdwpClient`.
+impl<T> Drop for ClientInner<T> {
+    fn drop(&mut self) {
+        if !*self.disposed.get_mut() && *self.state.borrow() == ConnectionState::Connected {
+            (self.dispose_on_drop)();
+        }
+
+        // The reader task holds on to the stream, which would otherwise keep it open
+        for task in self.connection_tasks.get_mut().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdwp::{JdwpErrorCode, MockReply, MockVm, VariableLengthId};
+
+    fn event_composite(event: &[u8]) -> Vec<u8> {
+        let mut data = vec![1, 0, 0, 0, 1]; // suspend event thread, 1 event
+        data.extend(event);
+        data
+    }
+
+    fn location(class_id: u64, method_id: u64, index: u64) -> Vec<u8> {
+        let mut data = vec![1]; // class
+        data.extend(class_id.to_be_bytes());
+        data.extend(method_id.to_be_bytes());
+        data.extend(index.to_be_bytes());
+        data
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut data = (value.len() as u32).to_be_bytes().to_vec();
+        data.extend(value.as_bytes());
+        data
+    }
+
+    #[tokio::test]
+    async fn test_new_negotiates_session() {
+        let (client, vm) = MockVm::new()
+            .with_id_sizes(JdwpIdSizes {
+                field_id_size: 4,
+                method_id_size: 4,
+                object_id_size: 3,
+                reference_type_id_size: 4,
+                frame_id_size: 8,
+            })
+            .with_capabilities(Capabilities {
+                can_pop_frames: true,
+                ..Default::default()
+            })
+            .connect()
+            .await
+            .unwrap();
+
+        let session = client.session();
+        assert_eq!(session.id_sizes.object_id_size, 3);
+        assert_eq!(session.version.description.string, "Mock VM");
+        assert!(session.capabilities.can_pop_frames);
+        assert!(vm.has_received(Command::VirtualMachineCapabilitiesNew));
+
+        // Bodies are encoded with the negotiated sizes
+        client
+            .thread_get_name(ThreadId(VariableLengthId { value: 5 }))
+            .await
+            .unwrap_err();
+        let received = vm.received();
+        assert_eq!(received.last().unwrap().data, [0, 0, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_error_reply_is_jdwp_error() {
+        let (client, _vm) = MockVm::new()
+            .reply_error(Command::ThreadReferenceName, JdwpErrorCode::InvalidThread)
+            .connect()
+            .await
+            .unwrap();
+
+        let error = client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            result::Error::JdwpError(JdwpErrorCode::InvalidThread)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_capability_fails_without_request() {
+        let (client, vm) = MockVm::new().connect().await.unwrap();
+
+        let error = client
+            .redefine_classes(vec![(
+                ReferenceTypeId(VariableLengthId { value: 1 }),
+                vec![0xca, 0xfe],
+            )])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            result::Error::CapabilityMissing("canRedefineClasses")
+        ));
+        assert!(!vm.has_received(Command::VirtualMachineRedefineClasses));
+    }
+
+    #[tokio::test]
+    async fn test_redefine_class_rejects_malformed_class_file() {
+        let (client, vm) = MockVm::new().connect().await.unwrap();
+
+        let mut class_bytes = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52];
+        class_bytes.extend([0, 2]); // constant pool count
+        class_bytes.extend([1, 0, 3]); // #1 Utf8
+        class_bytes.extend(b"Foo");
+        class_bytes.extend([0, 0x21]); // access flags
+        class_bytes.extend([0, 1]); // this_class, which is not a Class entry
+        class_bytes.extend([0; 10]); // super_class and empty tables
+
+        let error = client.redefine_class(class_bytes).await.unwrap_err();
+        assert!(matches!(error, result::Error::ParsingError { .. }));
+        assert!(!vm.has_received(Command::VirtualMachineClassesBySignature));
+    }
+
+    #[tokio::test]
+    async fn test_watchpoint_instance_filter_requires_capability() {
+        let (client, vm) = MockVm::new()
+            .with_capabilities(Capabilities {
+                can_watch_field_access: true,
+                ..Default::default()
+            })
+            .connect()
+            .await
+            .unwrap();
+
+        let error = client
+            .set_field_access_watchpoint(
+                ReferenceTypeId(VariableLengthId { value: 1 }),
+                "count",
+                Some(ObjectId(VariableLengthId { value: 2 })),
+                SuspendPolicy::All,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            result::Error::CapabilityMissing("canUseInstanceFilters")
+        ));
+        assert!(!vm.has_received(Command::EventRequestSet));
+    }
+
+    #[tokio::test]
+    async fn test_next_event_receives_pushed_event() {
+        let (client, vm) = MockVm::new().connect().await.unwrap();
+
+        let mut breakpoint = vec![2, 0, 0, 0, 3]; // Breakpoint, request 3
+        breakpoint.extend(7u64.to_be_bytes()); // thread
+        breakpoint.extend(location(0x10, 0x20, 4));
+        vm.push_event(event_composite(&breakpoint));
+
+        let composite = client.next_event().await.unwrap();
+        assert_eq!(composite.suspend_policy, SuspendPolicy::EventThread);
+        match &composite.events[0] {
+            Event::Breakpoint {
+                request_id,
+                thread,
+                location,
+            } => {
+                assert_eq!(*request_id, RequestId(3));
+                assert_eq!(thread.0.value, 7);
+                assert_eq!(location.index, 4);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_step_returns_new_location() {
+        let mut single_step = vec![1, 0, 0, 0, 9]; // SingleStep, request 9
+        single_step.extend(7u64.to_be_bytes());
+        single_step.extend(location(0x10, 0x20, 12));
+
+        let (client, vm) = MockVm::new()
+            .reply(Command::EventRequestSet, 9i32.to_be_bytes().to_vec())
+            .reply(Command::ThreadReferenceResume, vec![])
+            .reply(Command::EventRequestClear, vec![])
+            .push_event_after(
+                Command::ThreadReferenceResume,
+                event_composite(&single_step),
+            )
+            .connect()
+            .await
+            .unwrap();
+
+        let location = client
+            .step(
+                ThreadId(VariableLengthId { value: 7 }),
+                StepSize::Line,
+                StepDepth::Over,
+            )
+            .await
+            .unwrap();
+        assert_eq!(location.index, 12);
+        assert!(vm.has_received(Command::EventRequestClear));
+    }
+
+    #[tokio::test]
+    async fn test_failed_step_does_not_leak_waiter() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::EventRequestSet, 9i32.to_be_bytes().to_vec())
+            .reply_error(Command::ThreadReferenceResume, JdwpErrorCode::InvalidThread)
+            .reply(Command::EventRequestClear, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        let error = client
+            .step(
+                ThreadId(VariableLengthId { value: 7 }),
+                StepSize::Line,
+                StepDepth::Over,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            result::Error::JdwpError(JdwpErrorCode::InvalidThread)
+        ));
+        assert!(vm.has_received(Command::EventRequestClear));
+        assert!(client.inner.event_waiters.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_step_does_not_leak_waiter() {
+        let (client, _vm) = MockVm::new()
+            .reply(Command::EventRequestSet, 9i32.to_be_bytes().to_vec())
+            .reply(Command::ThreadReferenceResume, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        let step = client.step(
+            ThreadId(VariableLengthId { value: 7 }),
+            StepSize::Line,
+            StepDepth::Over,
+        );
+        assert!(timeout(Duration::from_millis(50), step).await.is_err());
+        assert!(client.inner.event_waiters.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deferred_breakpoint_is_installed_on_class_prepare() {
+        let mut class_prepare = vec![8, 0, 0, 0, 1]; // ClassPrepare, request 1
+        class_prepare.extend(7u64.to_be_bytes()); // thread
+        class_prepare.push(1); // class
+        class_prepare.extend(0x10u64.to_be_bytes());
+        class_prepare.extend(string("Lcom/example/Foo;"));
+        class_prepare.extend(7i32.to_be_bytes()); // status
+
+        let mut methods = 1i32.to_be_bytes().to_vec();
+        methods.extend(0x20u64.to_be_bytes());
+        methods.extend(string("run"));
+        methods.extend(string("()V"));
+        methods.extend(1i32.to_be_bytes());
+
+        let mut line_table = 0u64.to_be_bytes().to_vec();
+        line_table.extend(20u64.to_be_bytes());
+        line_table.extend(2i32.to_be_bytes());
+        line_table.extend(0u64.to_be_bytes());
+        line_table.extend(41i32.to_be_bytes());
+        line_table.extend(6u64.to_be_bytes());
+        line_table.extend(42i32.to_be_bytes());
+
+        // Only sent once the loading thread is resumed after installing the breakpoint
+        let mut hit = vec![2, 0, 0, 0, 2]; // Breakpoint, request 2
+        hit.extend(7u64.to_be_bytes());
+        hit.extend(location(0x10, 0x20, 6));
+
+        let mut next_request_id = 0i32;
+        let (client, vm) = MockVm::new()
+            .reply(Command::VirtualMachineClassesBySignature, vec![0, 0, 0, 0])
+            .on(Command::EventRequestSet, move |_| {
+                next_request_id += 1;
+                MockReply::Data(next_request_id.to_be_bytes().to_vec())
+            })
+            .reply(Command::ReferenceTypeMethods, methods)
+            .reply(Command::MethodLineTable, line_table)
+            .reply(Command::ThreadReferenceResume, vec![])
+            .push_event_after(Command::ThreadReferenceResume, event_composite(&hit))
+            .connect()
+            .await
+            .unwrap();
+
+        let breakpoint = client
+            .set_line_breakpoint("com.example.Foo", 42, SuspendPolicy::All)
+            .await
+            .unwrap();
+        assert_eq!(breakpoint, LineBreakpoint::Deferred(RequestId(1)));
+
+        vm.push_event(event_composite(&class_prepare));
+
+        // The class prepare event is consumed, the breakpoint it installed is reported
+        let composite = client.next_event().await.unwrap();
+        assert_eq!(composite.events[0].request_id(), RequestId(2));
+
+        let received = vm.received();
+        let breakpoint_request = received
+            .iter()
+            .filter(|received| received.command == Command::EventRequestSet)
+            .nth(1)
+            .unwrap();
+        let mut expected = vec![2, 2, 0, 0, 0, 1, 7]; // Breakpoint, all, LocationOnly
+        expected.extend(location(0x10, 0x20, 6));
+        assert_eq!(breakpoint_request.data, expected);
+        assert!(vm.has_received(Command::ThreadReferenceResume));
+    }
+
+    #[tokio::test]
+    async fn test_loaded_class_breakpoint_is_installed_once() {
+        let mut loaded = 1i32.to_be_bytes().to_vec();
+        loaded.push(1); // class
+        loaded.extend(0x10u64.to_be_bytes());
+        loaded.extend(7i32.to_be_bytes()); // status
+
+        let mut methods = 1i32.to_be_bytes().to_vec();
+        methods.extend(0x20u64.to_be_bytes());
+        methods.extend(string("run"));
+        methods.extend(string("()V"));
+        methods.extend(1i32.to_be_bytes());
+
+        let mut line_table = 0u64.to_be_bytes().to_vec();
+        line_table.extend(20u64.to_be_bytes());
+        line_table.extend(1i32.to_be_bytes());
+        line_table.extend(6u64.to_be_bytes());
+        line_table.extend(42i32.to_be_bytes());
+
+        // The class is prepared after the ClassPrepare request was set, so it is both
+        // found loaded and reported by an event, which arrives while the breakpoint is
+        // being installed
+        let mut class_prepare = vec![8, 0, 0, 0, 1]; // ClassPrepare, request 1
+        class_prepare.extend(7u64.to_be_bytes()); // thread
+        class_prepare.push(1); // class
+        class_prepare.extend(0x10u64.to_be_bytes());
+        class_prepare.extend(string("Lcom/example/Foo;"));
+        class_prepare.extend(7i32.to_be_bytes()); // status
+
+        let mut hit = vec![2, 0, 0, 0, 2]; // Breakpoint, request 2
+        hit.extend(7u64.to_be_bytes());
+        hit.extend(location(0x10, 0x20, 6));
+
+        let mut next_request_id = 0i32;
+        let (client, vm) = MockVm::new()
+            .reply(Command::VirtualMachineClassesBySignature, loaded)
+            .on(Command::EventRequestSet, move |_| {
+                next_request_id += 1;
+                MockReply::Data(next_request_id.to_be_bytes().to_vec())
+            })
+            .reply(Command::EventRequestClear, vec![])
+            .reply(Command::ReferenceTypeMethods, methods)
+            .reply(Command::MethodLineTable, line_table)
+            .reply(Command::ThreadReferenceResume, vec![])
+            .push_event_after(
+                Command::ReferenceTypeMethods,
+                event_composite(&class_prepare),
+            )
+            .push_event_after(Command::ThreadReferenceResume, event_composite(&hit))
+            .connect()
+            .await
+            .unwrap();
+
+        let breakpoint = client
+            .set_line_breakpoint("com.example.Foo", 42, SuspendPolicy::All)
+            .await
+            .unwrap();
+        assert_eq!(
+            breakpoint,
+            LineBreakpoint::Installed {
+                class_prepare: RequestId(1),
+                breakpoints: vec![RequestId(2)],
+            }
+        );
+
+        // The class prepare event is consumed without installing the breakpoint again
+        let composite = client.next_event().await.unwrap();
+        assert_eq!(composite.events[0].request_id(), RequestId(2));
+        assert!(vm.has_received(Command::ThreadReferenceResume));
+        let sets = vm
+            .received()
+            .iter()
+            .filter(|received| received.command == Command::EventRequestSet)
+            .count();
+        assert_eq!(sets, 2);
+
+        client.clear_line_breakpoint(&breakpoint).await.unwrap();
+        let clears: Vec<_> = vm
+            .received()
+            .into_iter()
+            .filter(|received| received.command == Command::EventRequestClear)
+            .map(|received| received.data)
+            .collect();
+        assert_eq!(clears, [vec![8, 0, 0, 0, 1], vec![2, 0, 0, 0, 2]]);
+    }
+
+    #[tokio::test]
+    async fn test_class_prepare_with_other_events_resumes_thread() {
+        let mut composite = vec![1, 0, 0, 0, 2]; // suspend event thread, 2 events
+        composite.extend([8, 0, 0, 0, 1]); // ClassPrepare, request 1
+        composite.extend(7u64.to_be_bytes()); // thread
+        composite.push(1); // class
+        composite.extend(0x10u64.to_be_bytes());
+        composite.extend(string("Lcom/example/Foo;"));
+        composite.extend(7i32.to_be_bytes()); // status
+        composite.extend([6, 0, 0, 0, 5]); // ThreadStart, request 5
+        composite.extend(7u64.to_be_bytes()); // thread
+
+        let mut thread_death = vec![7, 0, 0, 0, 6]; // ThreadDeath, request 6
+        thread_death.extend(7u64.to_be_bytes());
+
+        let mut next_request_id = 0i32;
+        let (client, vm) = MockVm::new()
+            .reply(Command::VirtualMachineClassesBySignature, vec![0, 0, 0, 0])
+            .on(Command::EventRequestSet, move |_| {
+                next_request_id += 1;
+                MockReply::Data(next_request_id.to_be_bytes().to_vec())
+            })
+            .reply(Command::ReferenceTypeMethods, vec![0, 0, 0, 0])
+            .reply(Command::ThreadReferenceResume, vec![])
+            .push_event_after(
+                Command::ThreadReferenceResume,
+                event_composite(&thread_death),
+            )
+            .connect()
+            .await
+            .unwrap();
+
+        client
+            .set_line_breakpoint("com.example.Foo", 42, SuspendPolicy::All)
+            .await
+            .unwrap();
+        vm.push_event(composite);
+
+        // Only the other event is reported, and the loading thread is resumed anyway
+        let composite = client.next_event().await.unwrap();
+        assert_eq!(composite.events.len(), 1);
+        assert_eq!(composite.events[0].request_id(), RequestId(5));
+        let composite = client.next_event().await.unwrap();
+        assert_eq!(composite.events[0].request_id(), RequestId(6));
+        assert!(vm.has_received(Command::ThreadReferenceResume));
+    }
+
+    #[tokio::test]
+    async fn test_deferred_breakpoint_is_installed_during_step() {
+        let mut class_prepare = vec![8, 0, 0, 0, 1]; // ClassPrepare, request 1
+        class_prepare.extend(8u64.to_be_bytes()); // thread
+        class_prepare.push(1); // class
+        class_prepare.extend(0x10u64.to_be_bytes());
+        class_prepare.extend(string("Lcom/example/Foo;"));
+        class_prepare.extend(7i32.to_be_bytes()); // status
+
+        let mut methods = 1i32.to_be_bytes().to_vec();
+        methods.extend(0x20u64.to_be_bytes());
+        methods.extend(string("run"));
+        methods.extend(string("()V"));
+        methods.extend(1i32.to_be_bytes());
+
+        let mut line_table = 0u64.to_be_bytes().to_vec();
+        line_table.extend(20u64.to_be_bytes());
+        line_table.extend(1i32.to_be_bytes());
+        line_table.extend(6u64.to_be_bytes());
+        line_table.extend(42i32.to_be_bytes());
+
+        let mut single_step = vec![1, 0, 0, 0, 2]; // SingleStep, request 2
+        single_step.extend(7u64.to_be_bytes());
+        single_step.extend(location(0x30, 0x40, 12));
+
+        // Stepping thread 7 lets thread 8 load the class, and the step only completes
+        // once thread 8 was resumed after installing the breakpoint
+        let mut next_request_id = 0i32;
+        let (client, vm) = MockVm::new()
+            .reply(Command::VirtualMachineClassesBySignature, vec![0, 0, 0, 0])
+            .on(Command::EventRequestSet, move |_| {
+                next_request_id += 1;
+                MockReply::Data(next_request_id.to_be_bytes().to_vec())
+            })
+            .reply(Command::EventRequestClear, vec![])
+            .reply(Command::ReferenceTypeMethods, methods)
+            .reply(Command::MethodLineTable, line_table)
+            .reply(Command::ThreadReferenceResume, vec![])
+            .push_event_after(
+                Command::ThreadReferenceResume,
+                event_composite(&class_prepare),
+            )
+            .push_event_after(
+                Command::ThreadReferenceResume,
+                event_composite(&single_step),
+            )
+            .connect()
+            .await
+            .unwrap();
+
+        client
+            .set_line_breakpoint("com.example.Foo", 42, SuspendPolicy::All)
+            .await
+            .unwrap();
+        let stepped = client
+            .step(
+                ThreadId(VariableLengthId { value: 7 }),
+                StepSize::Line,
+                StepDepth::Over,
+            )
+            .await
+            .unwrap();
+        assert_eq!(stepped.index, 12);
+
+        let received = vm.received();
+        let breakpoint_request = received
+            .iter()
+            .filter(|received| received.command == Command::EventRequestSet)
+            .nth(2)
+            .unwrap();
+        let mut expected = vec![2, 2, 0, 0, 0, 1, 7]; // Breakpoint, all, LocationOnly
+        expected.extend(location(0x10, 0x20, 6));
+        assert_eq!(breakpoint_request.data, expected);
+        let resumed: Vec<_> = received
+            .iter()
+            .filter(|received| received.command == Command::ThreadReferenceResume)
+            .map(|received| received.data.clone())
+            .collect();
+        assert_eq!(resumed, [7u64.to_be_bytes(), 8u64.to_be_bytes()]);
+    }
+
+    #[tokio::test]
+    async fn test_lost_connection_fails_requests() {
+        let (client, vm) = MockVm::new().connect().await.unwrap();
+        let mut state = client.connection_state();
+        assert_eq!(*state.borrow(), ConnectionState::Connected);
+
+        vm.disconnect();
+        state
+            .wait_for(|state| *state == ConnectionState::Disconnected)
+            .await
+            .unwrap();
+        let error = client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, result::Error::Disconnected));
+        assert!(client.next_event().await.is_none());
+        vm.stopped().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_vm_death_fails_requests() {
+        let (client, vm) = MockVm::new().connect().await.unwrap();
+
+        vm.push_event(event_composite(&[99, 0, 0, 0, 0])); // VMDeath
+        let composite = client.next_event().await.unwrap();
+        assert!(matches!(composite.events[0], Event::VmDeath { .. }));
+        assert_eq!(*client.connection_state().borrow(), ConnectionState::VmDead);
+        let error = client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, result::Error::VmDead));
+        assert!(!vm.has_received(Command::ThreadReferenceName));
+
+        vm.disconnect();
+        assert!(client.next_event().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_request_reconnects_after_lost_connection() {
+        let vms = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let connected = vms.clone();
+        let client = JdwpClient::connect_with_reconnect(
+            move || {
+                let (client_stream, vm_stream) = tokio::io::duplex(64 * 1024);
+                let vm = MockVm::new()
+                    .reply(Command::ThreadReferenceName, string("main"))
+                    .spawn(vm_stream);
+                connected.lock().unwrap().push(vm);
+                async move { Ok(client_stream) }
+            },
+            ReconnectPolicy {
+                max_attempts: 1,
+                delay: Duration::ZERO,
+            },
+        )
+        .await
+        .unwrap();
+
+        vms.lock().unwrap()[0].disconnect();
+        client
+            .connection_state()
+            .wait_for(|state| *state == ConnectionState::Disconnected)
+            .await
+            .unwrap();
+
+        // The thread ID belongs to the lost connection, so the request reconnects but
+        // is not sent
+        let error = client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, result::Error::Disconnected));
+        assert_eq!(
+            *client.connection_state().borrow(),
+            ConnectionState::Connected
+        );
+        {
+            let vms = vms.lock().unwrap();
+            assert_eq!(vms.len(), 2);
+            assert!(vms[1].has_received(Command::VirtualMachineIDSizes));
+            assert!(!vms[1].has_received(Command::ThreadReferenceName));
+        }
+
+        let name = client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap();
+        assert_eq!(name.string, "main");
+    }
+
+    #[tokio::test]
+    async fn test_request_without_ids_is_sent_after_reconnect() {
+        let vms = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let connected = vms.clone();
+        let client = JdwpClient::connect_with_reconnect(
+            move || {
+                let (client_stream, vm_stream) = tokio::io::duplex(64 * 1024);
+                connected
+                    .lock()
+                    .unwrap()
+                    .push(MockVm::new().spawn(vm_stream));
+                async move { Ok(client_stream) }
+            },
+            ReconnectPolicy {
+                max_attempts: 1,
+                delay: Duration::ZERO,
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut state = client.connection_state();
+        vms.lock().unwrap()[0].disconnect();
+        state
+            .wait_for(|state| *state == ConnectionState::Disconnected)
+            .await
+            .unwrap();
+
+        // Negotiation finishes before the client is reported as connected again
+        let (version, _) = tokio::join!(client.vm_get_version(), async {
+            state
+                .wait_for(|state| *state == ConnectionState::Connected)
+                .await
+                .unwrap();
+            assert!(vms.lock().unwrap()[1].has_received(Command::VirtualMachineCapabilitiesNew));
+        });
+        assert_eq!(version.unwrap().description.string, "Mock VM");
+    }
+
+    #[tokio::test]
+    async fn test_pinned_object_is_released_with_next_request() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::ObjectReferenceDisableCollection, vec![])
+            .reply(Command::ObjectReferenceEnableCollection, vec![])
+            .reply(Command::ThreadReferenceName, string("main"))
+            .connect()
+            .await
+            .unwrap();
+
+        let object = ObjectId(VariableLengthId { value: 3 });
+        let first = client.pin_object(object).await.unwrap();
+        let second = client.pin_object(object).await.unwrap();
+        drop(first);
+        client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap();
+        assert!(!vm.has_received(Command::ObjectReferenceEnableCollection));
+
+        drop(second);
+        client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap();
+        let commands: Vec<_> = vm
+            .received()
+            .into_iter()
+            .map(|received| received.command)
+            .filter(|command| {
+                matches!(
+                    command,
+                    Command::ObjectReferenceDisableCollection
+                        | Command::ObjectReferenceEnableCollection
+                )
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                Command::ObjectReferenceDisableCollection,
+                Command::ObjectReferenceEnableCollection
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_pins_share_disable_collection() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::ObjectReferenceDisableCollection, vec![])
+            .reply(Command::ObjectReferenceEnableCollection, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        let object = ObjectId(VariableLengthId { value: 3 });
+        let (first, second) = tokio::join!(client.pin_object(object), client.pin_object(object));
+        drop((first.unwrap(), second.unwrap()));
+        client.flush_object_references().await.unwrap();
+        let commands: Vec<_> = vm
+            .received()
+            .into_iter()
+            .map(|received| received.command)
+            .filter(|command| {
+                matches!(
+                    command,
+                    Command::ObjectReferenceDisableCollection
+                        | Command::ObjectReferenceEnableCollection
+                )
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                Command::ObjectReferenceDisableCollection,
+                Command::ObjectReferenceEnableCollection
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_pin_does_not_enable_collection() {
+        let (client, vm) = MockVm::new()
+            .reply_error(
+                Command::ObjectReferenceDisableCollection,
+                JdwpErrorCode::InvalidObject,
+            )
+            .reply(Command::ObjectReferenceEnableCollection, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        let object = ObjectId(VariableLengthId { value: 3 });
+        let error = client.pin_object(object).await.err().unwrap();
+        assert!(matches!(
+            error,
+            result::Error::JdwpError(JdwpErrorCode::InvalidObject)
+        ));
+        client.flush_object_references().await.unwrap();
+        assert!(!vm.has_received(Command::ObjectReferenceEnableCollection));
+    }
+
+    #[tokio::test]
+    async fn test_unencodable_releases_stay_queued() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::ObjectReferenceDisableCollection, vec![])
+            .reply(Command::ObjectReferenceEnableCollection, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        let object = ObjectId(VariableLengthId { value: 3 });
+        drop(client.pin_object(object).await.unwrap());
+        let sizes = client.inner.sizes.send_replace(None);
+        let error = client.flush_object_references().await.unwrap_err();
+        assert!(matches!(error, result::Error::IdSizesUnknown));
+        assert!(!vm.has_received(Command::ObjectReferenceEnableCollection));
+
+        client.inner.sizes.send_replace(sizes);
+        client.flush_object_references().await.unwrap();
+        assert!(vm.has_received(Command::ObjectReferenceEnableCollection));
+    }
+
+    #[tokio::test]
+    async fn test_disposed_references_are_counted_per_object() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::ObjectReferenceDisableCollection, vec![])
+            .reply(Command::VirtualMachineDisposeObjects, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        let object = ObjectId(VariableLengthId { value: 3 });
+        let pinned = ObjectId(VariableLengthId { value: 4 });
+        let _guard = client.pin_object(pinned).await.unwrap();
+        client.dispose_object(object);
+        client.dispose_object(object);
+        client.dispose_object(pinned);
+        client.flush_object_references().await.unwrap();
+
+        let received = vm.received();
+        let dispose = received
+            .iter()
+            .find(|received| received.command == Command::VirtualMachineDisposeObjects)
+            .unwrap();
+        let mut expected = 1i32.to_be_bytes().to_vec();
+        expected.extend(3u64.to_be_bytes());
+        expected.extend(2i32.to_be_bytes());
+        assert_eq!(dispose.data, expected);
+    }
+
+    #[tokio::test]
+    async fn test_dispose_detaches_client() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::VirtualMachineDispose, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        client.vm_dispose().await.unwrap();
+        let error = client
+            .thread_get_name(ThreadId(VariableLengthId { value: 1 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, result::Error::Disconnected));
+        assert!(client.next_event().await.is_none());
+
+        // Dropping a disposed client must not dispose again
+        drop(client);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let disposals = vm
+            .received()
+            .iter()
+            .filter(|received| received.command == Command::VirtualMachineDispose)
+            .count();
+        assert_eq!(disposals, 1);
+    }
+
+    #[tokio::test]
+    async fn test_close_disposes_vm() {
+        let (client, vm) = MockVm::new()
+            .reply(Command::VirtualMachineDispose, vec![])
+            .connect()
+            .await
+            .unwrap();
+
+        // Dispose has been answered by the time close returns
+        client.close().await.unwrap();
+        let disposals = vm
+            .received()
+            .iter()
+            .filter(|received| received.command == Command::VirtualMachineDispose)
+            .count();
+        assert_eq!(disposals, 1);
+    }
+
+    #[tokio::test]
+    async fn test_drop_disposes_vm() {
+        let (client, vm) = MockVm::new().connect().await.unwrap();
+
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !vm.has_received(Command::VirtualMachineDispose) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
 }
@@ -1,20 +1,108 @@
-use binrw::{BinRead, binrw};
+use binrw::{BinRead, BinWrite, binrw};
+use std::io::Read;
 
 use crate::{
     binrw_enum,
-    jdwp::{ClassStatus, JdwpIdSize, JdwpIdSizes, JdwpString, TypeTag},
+    bytecode::Instruction,
+    descriptors::{
+        FieldDescriptor, MethodDescriptor, parse_field_descriptor, parse_method_descriptor,
+    },
+    java_class_file::{FieldAccessFlags, MethodAccessFlags},
+    jdwp::{
+        ClassStatus, EventKind, FieldId, FrameId, InvokeOptions, JdwpIdSize, JdwpIdSizes,
+        JdwpString, Location, MethodId, ObjectId, ReferenceTypeId, StepDepth, StepSize,
+        SuspendPolicy, SuspendStatus, Tag, TaggedObjectId, ThreadGroupId, ThreadId, ThreadStatus,
+        TypeTag, Value,
+        types::{read_count, vec_for_count},
+    },
 };
 
 binrw_enum! {
     #[repr(u16)]
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub enum Command {
-        VirtualMachineVersion =     (1 << 8) | 1,
-        VirtualMachineAllClasses =  (1 << 8) | 3,
-        VirtualMachineIDSizes =     (1 << 8) | 7,
+        VirtualMachineVersion =                  (1 << 8) | 1,
+        VirtualMachineClassesBySignature =       (1 << 8) | 2,
+        VirtualMachineAllClasses =               (1 << 8) | 3,
+        VirtualMachineAllThreads =               (1 << 8) | 4,
+        VirtualMachineTopLevelThreadGroups =     (1 << 8) | 5,
+        VirtualMachineDispose =                  (1 << 8) | 6,
+        VirtualMachineIDSizes =                  (1 << 8) | 7,
+        VirtualMachineSuspend =                  (1 << 8) | 8,
+        VirtualMachineResume =                   (1 << 8) | 9,
+        VirtualMachineExit =                     (1 << 8) | 10,
+        VirtualMachineCapabilities =             (1 << 8) | 12,
+        VirtualMachineDisposeObjects =           (1 << 8) | 14,
+        VirtualMachineHoldEvents =               (1 << 8) | 15,
+        VirtualMachineReleaseEvents =            (1 << 8) | 16,
+        VirtualMachineCapabilitiesNew =          (1 << 8) | 17,
+        VirtualMachineRedefineClasses =          (1 << 8) | 18,
+        ReferenceTypeSignature =                 (2 << 8) | 1,
+        ReferenceTypeClassLoader =               (2 << 8) | 2,
+        ReferenceTypeModifiers =                 (2 << 8) | 3,
+        ReferenceTypeFields =                    (2 << 8) | 4,
+        ReferenceTypeMethods =                   (2 << 8) | 5,
+        ReferenceTypeGetValues =                 (2 << 8) | 6,
+        ReferenceTypeSourceFile =                (2 << 8) | 7,
+        ReferenceTypeStatus =                    (2 << 8) | 9,
+        ReferenceTypeInterfaces =                (2 << 8) | 10,
+        ReferenceTypeClassObject =               (2 << 8) | 11,
+        ReferenceTypeFieldsWithGeneric =         (2 << 8) | 14,
+        ReferenceTypeMethodsWithGeneric =        (2 << 8) | 15,
+        ClassTypeInvokeMethod =                  (3 << 8) | 3,
+        ClassTypeNewInstance =                   (3 << 8) | 4,
+        InterfaceTypeInvokeMethod =              (5 << 8) | 1,
+        MethodLineTable =                        (6 << 8) | 1,
+        MethodVariableTable =                    (6 << 8) | 2,
+        MethodBytecodes =                        (6 << 8) | 3,
+        MethodVariableTableWithGeneric =         (6 << 8) | 5,
+        ObjectReferenceReferenceType =           (9 << 8) | 1,
+        ObjectReferenceGetValues =               (9 << 8) | 2,
+        ObjectReferenceSetValues =               (9 << 8) | 3,
+        ObjectReferenceMonitorInfo =             (9 << 8) | 5,
+        ObjectReferenceInvokeMethod =            (9 << 8) | 6,
+        ObjectReferenceDisableCollection =       (9 << 8) | 7,
+        ObjectReferenceEnableCollection =        (9 << 8) | 8,
+        StringReferenceValue =                   (10 << 8) | 1,
+        ThreadReferenceName =                    (11 << 8) | 1,
+        ThreadReferenceSuspend =                 (11 << 8) | 2,
+        ThreadReferenceResume =                  (11 << 8) | 3,
+        ThreadReferenceStatus =                  (11 << 8) | 4,
+        ThreadReferenceThreadGroup =             (11 << 8) | 5,
+        ThreadReferenceFrames =                  (11 << 8) | 6,
+        ThreadReferenceFrameCount =              (11 << 8) | 7,
+        ThreadReferenceOwnedMonitors =           (11 << 8) | 8,
+        ThreadReferenceCurrentContendedMonitor = (11 << 8) | 9,
+        ThreadReferenceSuspendCount =            (11 << 8) | 12,
+        ThreadReferenceForceEarlyReturn =        (11 << 8) | 14,
+        StackFrameGetValues =                    (16 << 8) | 1,
+        StackFrameSetValues =                    (16 << 8) | 2,
+        StackFrameThisObject =                   (16 << 8) | 3,
+        StackFramePopFrames =                    (16 << 8) | 4,
+        ArrayReferenceLength =                   (13 << 8) | 1,
+        ArrayReferenceGetValues =                (13 << 8) | 2,
+        ArrayReferenceSetValues =                (13 << 8) | 3,
+        EventRequestSet =                        (15 << 8) | 1,
+        EventRequestClear =                      (15 << 8) | 2,
+        EventRequestClearAllBreakpoints =        (15 << 8) | 3,
+        EventComposite =                         (64 << 8) | 100,
     }
 }
 
+impl Command {
+    pub fn command_set(self) -> u8 {
+        (self as u16 >> 8) as u8
+    }
+
+    pub fn command(self) -> u8 {
+        self as u16 as u8
+    }
+}
+
+/// Set in the flags byte of every reply packet. Packets without it are commands,
+/// which the VM only sends for events.
+pub const REPLY_FLAG: u8 = 0x80;
+
 #[binrw]
 #[brw(big)]
 pub struct CommandPacketHeader {
@@ -25,7 +113,7 @@ pub struct CommandPacketHeader {
 }
 impl CommandPacketHeader {
     pub fn get_length() -> usize {
-        return 4 + 4 + 1 + 2;
+        4 + 4 + 1 + 2
     }
 }
 
@@ -37,8 +125,8 @@ pub struct ReplyPacketHeader {
     pub flags: u8,
     pub error_code: u16,
 }
-impl ReplyPacketHeader {
-    pub fn default() -> Self {
+impl Default for ReplyPacketHeader {
+    fn default() -> Self {
         ReplyPacketHeader {
             length: 0,
             id: 0xFFFFFFFF,
@@ -46,15 +134,17 @@ impl ReplyPacketHeader {
             error_code: 0,
         }
     }
+}
+impl ReplyPacketHeader {
     pub fn get_length() -> usize {
-        return 4 + 4 + 1 + 2;
+        4 + 4 + 1 + 2
     }
     pub fn is_success(&self) -> bool {
-        return self.error_code == 0;
+        self.error_code == 0
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct VariableLengthId {
     pub value: u64,
 }
@@ -66,23 +156,54 @@ impl BinRead for VariableLengthId {
         endian: binrw::Endian,
         args: Self::Args<'_>,
     ) -> binrw::BinResult<Self> {
-        // TODO: Support non-power-of-2 sizes if needed
-        let val: u64 = match args {
-            1 => u8::read_options(reader, endian, ())? as u64,
-            2 => u16::read_options(reader, endian, ())? as u64,
-            4 => u32::read_options(reader, endian, ())? as u64,
-            8 => u64::read_options(reader, endian, ())?,
-            _ => {
-                return binrw::BinResult::Err(binrw::Error::Custom {
-                    pos: reader.stream_position().unwrap_or(0),
-                    err: Box::new("Unsupported variable size ID"),
-                });
+        let size = args as usize;
+        if !(1..=8).contains(&size) {
+            return binrw::BinResult::Err(binrw::Error::Custom {
+                pos: reader.stream_position().unwrap_or(0),
+                err: Box::new("Unsupported variable size ID"),
+            });
+        }
+
+        // IDs can have any size up to 8 bytes, so they are read into the matching end of a u64
+        let mut bytes = [0u8; 8];
+        let val = match endian {
+            binrw::Endian::Big => {
+                reader.read_exact(&mut bytes[8 - size..])?;
+                u64::from_be_bytes(bytes)
+            }
+            binrw::Endian::Little => {
+                reader.read_exact(&mut bytes[..size])?;
+                u64::from_le_bytes(bytes)
             }
         };
 
         Ok(VariableLengthId { value: val })
     }
 }
+impl BinWrite for VariableLengthId {
+    type Args<'a> = JdwpIdSize;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        let size = args as usize;
+        if !(1..=8).contains(&size) {
+            return binrw::BinResult::Err(binrw::Error::Custom {
+                pos: writer.stream_position().unwrap_or(0),
+                err: Box::new("Unsupported variable size ID"),
+            });
+        }
+
+        match endian {
+            binrw::Endian::Big => writer.write_all(&self.value.to_be_bytes()[8 - size..])?,
+            binrw::Endian::Little => writer.write_all(&self.value.to_le_bytes()[..size])?,
+        }
+        Ok(())
+    }
+}
 
 #[binrw]
 #[brw(big)]
@@ -109,7 +230,7 @@ pub struct IdSizesReply {
 #[derive(Debug)]
 pub struct AllClassesReplyClass {
     pub ref_type_tag: TypeTag,
-    pub type_id: VariableLengthId,
+    pub type_id: ReferenceTypeId,
     pub signature: JdwpString,
     pub status: ClassStatus,
 }
@@ -123,7 +244,7 @@ impl BinRead for AllClassesReplyClass {
     ) -> binrw::BinResult<Self> {
         Ok(AllClassesReplyClass {
             ref_type_tag: TypeTag::read_options(reader, endian, ())?,
-            type_id: VariableLengthId::read_options(reader, endian, args.reference_type_id_size)?,
+            type_id: ReferenceTypeId::read_options(reader, endian, args)?,
             signature: JdwpString::read_options(reader, endian, ())?,
             status: ClassStatus::read_options(reader, endian, ())?,
         })
@@ -142,8 +263,8 @@ impl BinRead for AllClassesReply {
         endian: binrw::Endian,
         args: Self::Args<'_>,
     ) -> binrw::BinResult<Self> {
-        let classes_length = i32::read_options(reader, endian, ())?;
-        let mut classes = Vec::with_capacity(classes_length as usize);
+        let classes_length = read_count(reader, endian)?;
+        let mut classes = vec_for_count(classes_length);
         for _ in 0..classes_length {
             classes.push(AllClassesReplyClass::read_options(reader, endian, args)?);
         }
@@ -152,17 +273,2223 @@ impl BinRead for AllClassesReply {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::jdwp::Command;
-    use binrw::BinRead;
-    use std::io::Cursor;
+#[derive(Debug)]
+pub struct ClassesBySignatureRequest {
+    pub signature: JdwpString,
+}
+impl BinWrite for ClassesBySignatureRequest {
+    type Args<'a> = JdwpIdSizes;
 
-    #[test]
-    fn test_deserialize_vm_version_command() {
-        let data = [1u8, 1u8];
-        let mut cursor = Cursor::new(&data);
-        let value = Command::read_be(&mut cursor).unwrap();
-        assert_eq!(value, Command::VirtualMachineVersion);
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        _args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.signature.write_options(writer, endian, ())
+    }
+}
+impl BinRead for ClassesBySignatureRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        _args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ClassesBySignatureRequest {
+            signature: JdwpString::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ClassesBySignatureReplyClass {
+    pub ref_type_tag: TypeTag,
+    pub type_id: ReferenceTypeId,
+    pub status: ClassStatus,
+}
+
+/// All loaded classes with a given signature, one per class loader which loaded it.
+#[derive(Debug)]
+pub struct ClassesBySignatureReply {
+    pub classes: Vec<ClassesBySignatureReplyClass>,
+}
+impl BinRead for ClassesBySignatureReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let classes_length = read_count(reader, endian)?;
+        let mut classes = vec_for_count(classes_length);
+        for _ in 0..classes_length {
+            classes.push(ClassesBySignatureReplyClass {
+                ref_type_tag: TypeTag::read_options(reader, endian, ())?,
+                type_id: ReferenceTypeId::read_options(reader, endian, args)?,
+                status: ClassStatus::read_options(reader, endian, ())?,
+            });
+        }
+
+        Ok(ClassesBySignatureReply { classes })
+    }
+}
+
+/// Optional features of the VM as reported by `VirtualMachine.CapabilitiesNew`. VMs which
+/// only support `VirtualMachine.Capabilities` report the first seven.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub can_watch_field_modification: bool,
+    pub can_watch_field_access: bool,
+    pub can_get_bytecodes: bool,
+    pub can_get_synthetic_attribute: bool,
+    pub can_get_owned_monitor_info: bool,
+    pub can_get_current_contended_monitor: bool,
+    pub can_get_monitor_info: bool,
+    pub can_redefine_classes: bool,
+    pub can_add_method: bool,
+    pub can_unrestrictedly_redefine_classes: bool,
+    pub can_pop_frames: bool,
+    pub can_use_instance_filters: bool,
+    pub can_get_source_debug_extension: bool,
+    pub can_request_vm_death_event: bool,
+    pub can_set_default_stratum: bool,
+    pub can_get_instance_info: bool,
+    pub can_request_monitor_events: bool,
+    pub can_get_monitor_frame_info: bool,
+    pub can_use_source_name_filters: bool,
+    pub can_get_constant_pool: bool,
+    pub can_force_early_return: bool,
+}
+impl BinRead for Capabilities {
+    type Args<'a> = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        // 21 capabilities followed by 11 reserved ones
+        let flags = <[u8; 32]>::read_options(reader, endian, args)?;
+        Ok(Capabilities {
+            can_watch_field_modification: flags[0] != 0,
+            can_watch_field_access: flags[1] != 0,
+            can_get_bytecodes: flags[2] != 0,
+            can_get_synthetic_attribute: flags[3] != 0,
+            can_get_owned_monitor_info: flags[4] != 0,
+            can_get_current_contended_monitor: flags[5] != 0,
+            can_get_monitor_info: flags[6] != 0,
+            can_redefine_classes: flags[7] != 0,
+            can_add_method: flags[8] != 0,
+            can_unrestrictedly_redefine_classes: flags[9] != 0,
+            can_pop_frames: flags[10] != 0,
+            can_use_instance_filters: flags[11] != 0,
+            can_get_source_debug_extension: flags[12] != 0,
+            can_request_vm_death_event: flags[13] != 0,
+            can_set_default_stratum: flags[14] != 0,
+            can_get_instance_info: flags[15] != 0,
+            can_request_monitor_events: flags[16] != 0,
+            can_get_monitor_frame_info: flags[17] != 0,
+            can_use_source_name_filters: flags[18] != 0,
+            can_get_constant_pool: flags[19] != 0,
+            can_force_early_return: flags[20] != 0,
+        })
+    }
+}
+
+impl BinWrite for Capabilities {
+    type Args<'a> = ();
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        let mut flags = [0u8; 32];
+        for (flag, value) in flags.iter_mut().zip([
+            self.can_watch_field_modification,
+            self.can_watch_field_access,
+            self.can_get_bytecodes,
+            self.can_get_synthetic_attribute,
+            self.can_get_owned_monitor_info,
+            self.can_get_current_contended_monitor,
+            self.can_get_monitor_info,
+            self.can_redefine_classes,
+            self.can_add_method,
+            self.can_unrestrictedly_redefine_classes,
+            self.can_pop_frames,
+            self.can_use_instance_filters,
+            self.can_get_source_debug_extension,
+            self.can_request_vm_death_event,
+            self.can_set_default_stratum,
+            self.can_get_instance_info,
+            self.can_request_monitor_events,
+            self.can_get_monitor_frame_info,
+            self.can_use_source_name_filters,
+            self.can_get_constant_pool,
+            self.can_force_early_return,
+        ]) {
+            *flag = value as u8;
+        }
+        flags.write_options(writer, endian, args)
+    }
+}
+
+/// Reply of the original `VirtualMachine.Capabilities` command.
+#[derive(Debug)]
+pub struct CapabilitiesReply {
+    pub can_watch_field_modification: bool,
+    pub can_watch_field_access: bool,
+    pub can_get_bytecodes: bool,
+    pub can_get_synthetic_attribute: bool,
+    pub can_get_owned_monitor_info: bool,
+    pub can_get_current_contended_monitor: bool,
+    pub can_get_monitor_info: bool,
+}
+impl BinRead for CapabilitiesReply {
+    type Args<'a> = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let flags = <[u8; 7]>::read_options(reader, endian, args)?;
+        Ok(CapabilitiesReply {
+            can_watch_field_modification: flags[0] != 0,
+            can_watch_field_access: flags[1] != 0,
+            can_get_bytecodes: flags[2] != 0,
+            can_get_synthetic_attribute: flags[3] != 0,
+            can_get_owned_monitor_info: flags[4] != 0,
+            can_get_current_contended_monitor: flags[5] != 0,
+            can_get_monitor_info: flags[6] != 0,
+        })
+    }
+}
+impl From<CapabilitiesReply> for Capabilities {
+    fn from(value: CapabilitiesReply) -> Self {
+        Capabilities {
+            can_watch_field_modification: value.can_watch_field_modification,
+            can_watch_field_access: value.can_watch_field_access,
+            can_get_bytecodes: value.can_get_bytecodes,
+            can_get_synthetic_attribute: value.can_get_synthetic_attribute,
+            can_get_owned_monitor_info: value.can_get_owned_monitor_info,
+            can_get_current_contended_monitor: value.can_get_current_contended_monitor,
+            can_get_monitor_info: value.can_get_monitor_info,
+            ..Default::default()
+        }
+    }
+}
+
+/// New bytes for a loaded class.
+#[derive(Debug)]
+pub struct ClassDefinition {
+    pub ref_type: ReferenceTypeId,
+    pub class_bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct RedefineClassesRequest {
+    pub classes: Vec<ClassDefinition>,
+}
+impl BinWrite for RedefineClassesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        (self.classes.len() as i32).write_options(writer, endian, ())?;
+        for class in self.classes.iter() {
+            class.ref_type.write_options(writer, endian, args)?;
+            (class.class_bytes.len() as i32).write_options(writer, endian, ())?;
+            class.class_bytes.write_options(writer, endian, ())?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for RedefineClassesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let classes_length = read_count(reader, endian)?;
+        let mut classes = vec_for_count(classes_length);
+        for _ in 0..classes_length {
+            let ref_type = ReferenceTypeId::read_options(reader, endian, args)?;
+            let bytes_length = read_count(reader, endian)?;
+            let mut class_bytes = vec_for_count(bytes_length);
+            reader
+                .by_ref()
+                .take(bytes_length as u64)
+                .read_to_end(&mut class_bytes)?;
+            if class_bytes.len() != bytes_length {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            classes.push(ClassDefinition {
+                ref_type,
+                class_bytes,
+            });
+        }
+
+        Ok(RedefineClassesRequest { classes })
+    }
+}
+
+/// Tells the VM that the debugger no longer uses `ref_count` of the references to `object`
+/// it has received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectReferenceCount {
+    pub object: ObjectId,
+    pub ref_count: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisposeObjectsRequest {
+    pub requests: Vec<ObjectReferenceCount>,
+}
+impl BinWrite for DisposeObjectsRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        (self.requests.len() as i32).write_options(writer, endian, ())?;
+        for request in self.requests.iter() {
+            request.object.write_options(writer, endian, args)?;
+            request.ref_count.write_options(writer, endian, ())?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for DisposeObjectsRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let requests_length = read_count(reader, endian)?;
+        let mut requests = vec_for_count(requests_length);
+        for _ in 0..requests_length {
+            requests.push(ObjectReferenceCount {
+                object: ObjectId::read_options(reader, endian, args)?,
+                ref_count: i32::read_options(reader, endian, ())?,
+            });
+        }
+
+        Ok(DisposeObjectsRequest { requests })
+    }
+}
+
+/// Identifies an event request. Events report the ID of the request which caused them.
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(pub i32);
+
+/// Restricts which events an event request reports.
+#[derive(Debug)]
+pub enum EventModifier {
+    Count(i32),
+    /// Only report events in classes whose name matches the pattern. Patterns may begin
+    /// or end with `*`, e.g. `java.*` or `*.Foo`.
+    ClassMatch(String),
+    /// Only report events in classes whose name does not match the pattern.
+    ClassExclude(String),
+    LocationOnly(Location),
+    /// Only report accesses or modifications of the given field.
+    FieldOnly {
+        ref_type: ReferenceTypeId,
+        field: FieldId,
+    },
+    /// Only report events whose `this` object is the given instance.
+    InstanceOnly(ObjectId),
+    /// Only report exceptions of the given type or its subtypes, or of any type if `None`.
+    ExceptionOnly {
+        exception: Option<ReferenceTypeId>,
+        caught: bool,
+        uncaught: bool,
+    },
+    Step {
+        thread: ThreadId,
+        size: StepSize,
+        depth: StepDepth,
+    },
+}
+impl EventModifier {
+    fn mod_kind(&self) -> u8 {
+        match self {
+            EventModifier::Count(_) => 1,
+            EventModifier::ClassMatch(_) => 5,
+            EventModifier::ClassExclude(_) => 6,
+            EventModifier::LocationOnly(_) => 7,
+            EventModifier::ExceptionOnly { .. } => 8,
+            EventModifier::FieldOnly { .. } => 9,
+            EventModifier::Step { .. } => 10,
+            EventModifier::InstanceOnly(_) => 11,
+        }
+    }
+}
+impl BinWrite for EventModifier {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.mod_kind().write_options(writer, endian, ())?;
+        match self {
+            EventModifier::Count(count) => count.write_options(writer, endian, ()),
+            EventModifier::ClassMatch(pattern) | EventModifier::ClassExclude(pattern) => {
+                JdwpString {
+                    string: pattern.clone(),
+                }
+                .write_options(writer, endian, ())
+            }
+            EventModifier::LocationOnly(location) => location.write_options(writer, endian, args),
+            EventModifier::ExceptionOnly {
+                exception,
+                caught,
+                uncaught,
+            } => {
+                // A null reference type matches any exception
+                exception
+                    .unwrap_or(ReferenceTypeId(VariableLengthId { value: 0 }))
+                    .write_options(writer, endian, args)?;
+                (*caught as u8).write_options(writer, endian, ())?;
+                (*uncaught as u8).write_options(writer, endian, ())
+            }
+            EventModifier::FieldOnly { ref_type, field } => {
+                ref_type.write_options(writer, endian, args)?;
+                field.write_options(writer, endian, args)
+            }
+            EventModifier::InstanceOnly(object) => object.write_options(writer, endian, args),
+            EventModifier::Step {
+                thread,
+                size,
+                depth,
+            } => {
+                thread.write_options(writer, endian, args)?;
+                size.write_options(writer, endian, ())?;
+                depth.write_options(writer, endian, ())
+            }
+        }
+    }
+}
+impl BinRead for EventModifier {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let mod_kind_pos = reader.stream_position()?;
+        let modifier = match u8::read_options(reader, endian, ())? {
+            1 => EventModifier::Count(i32::read_options(reader, endian, ())?),
+            5 => EventModifier::ClassMatch(JdwpString::read_options(reader, endian, ())?.string),
+            6 => EventModifier::ClassExclude(JdwpString::read_options(reader, endian, ())?.string),
+            7 => EventModifier::LocationOnly(Location::read_options(reader, endian, args)?),
+            8 => {
+                let exception = ReferenceTypeId::read_options(reader, endian, args)?;
+                EventModifier::ExceptionOnly {
+                    exception: (!exception.is_null()).then_some(exception),
+                    caught: u8::read_options(reader, endian, ())? != 0,
+                    uncaught: u8::read_options(reader, endian, ())? != 0,
+                }
+            }
+            9 => EventModifier::FieldOnly {
+                ref_type: ReferenceTypeId::read_options(reader, endian, args)?,
+                field: FieldId::read_options(reader, endian, args)?,
+            },
+            10 => EventModifier::Step {
+                thread: ThreadId::read_options(reader, endian, args)?,
+                size: StepSize::read_options(reader, endian, ())?,
+                depth: StepDepth::read_options(reader, endian, ())?,
+            },
+            11 => EventModifier::InstanceOnly(ObjectId::read_options(reader, endian, args)?),
+            other => {
+                return Err(binrw::Error::AssertFail {
+                    pos: mod_kind_pos,
+                    message: format!("Unsupported event modifier kind {}", other),
+                });
+            }
+        };
+
+        Ok(modifier)
+    }
+}
+
+#[derive(Debug)]
+pub struct EventRequestSet {
+    pub event_kind: EventKind,
+    pub suspend_policy: SuspendPolicy,
+    pub modifiers: Vec<EventModifier>,
+}
+impl BinWrite for EventRequestSet {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.event_kind.write_options(writer, endian, ())?;
+        self.suspend_policy.write_options(writer, endian, ())?;
+        (self.modifiers.len() as i32).write_options(writer, endian, ())?;
+        for modifier in self.modifiers.iter() {
+            modifier.write_options(writer, endian, args)?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for EventRequestSet {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let event_kind = EventKind::read_options(reader, endian, ())?;
+        let suspend_policy = SuspendPolicy::read_options(reader, endian, ())?;
+        let modifiers_length = read_count(reader, endian)?;
+        let mut modifiers = vec_for_count(modifiers_length);
+        for _ in 0..modifiers_length {
+            modifiers.push(EventModifier::read_options(reader, endian, args)?);
+        }
+
+        Ok(EventRequestSet {
+            event_kind,
+            suspend_policy,
+            modifiers,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct EventRequestClear {
+    pub event_kind: EventKind,
+    pub request_id: RequestId,
+}
+impl BinWrite for EventRequestClear {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        _: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.event_kind.write_options(writer, endian, ())?;
+        self.request_id.write_options(writer, endian, ())
+    }
+}
+impl BinRead for EventRequestClear {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        _: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(EventRequestClear {
+            event_kind: EventKind::read_options(reader, endian, ())?,
+            request_id: RequestId::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+fn read_generic_signature<R: std::io::Read + std::io::Seek>(
+    reader: &mut R,
+    endian: binrw::Endian,
+) -> binrw::BinResult<Option<String>> {
+    let signature = JdwpString::read_options(reader, endian, ())?;
+    if signature.string.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(signature.string))
+}
+
+/// A field declared by a reference type. The descriptor is parsed the same way as in
+/// locally read class files.
+#[derive(Debug)]
+pub struct ReferenceTypeField {
+    pub field_id: FieldId,
+    pub name: String,
+    pub descriptor: FieldDescriptor,
+    pub generic_signature: Option<String>,
+    pub access_flags: FieldAccessFlags,
+}
+impl ReferenceTypeField {
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+        with_generic: bool,
+    ) -> binrw::BinResult<Self> {
+        let field_id = FieldId::read_options(reader, endian, args)?;
+        let name = JdwpString::read_options(reader, endian, ())?;
+        let signature_pos = reader.stream_position()?;
+        let signature = JdwpString::read_options(reader, endian, ())?;
+        let descriptor =
+            parse_field_descriptor(&signature.string).map_err(|e| binrw::Error::Custom {
+                pos: signature_pos,
+                err: Box::new(format!(
+                    "Invalid field descriptor {}: {:?}",
+                    signature.string, e
+                )),
+            })?;
+        let generic_signature = if with_generic {
+            read_generic_signature(reader, endian)?
+        } else {
+            None
+        };
+        let mod_bits = i32::read_options(reader, endian, ())?;
+
+        Ok(ReferenceTypeField {
+            field_id,
+            name: name.string,
+            descriptor,
+            generic_signature,
+            access_flags: FieldAccessFlags::from_bits_retain(mod_bits as u16),
+        })
+    }
+
+    fn read_list<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+        with_generic: bool,
+    ) -> binrw::BinResult<Vec<Self>> {
+        let fields_length = read_count(reader, endian)?;
+        let mut fields = vec_for_count(fields_length);
+        for _ in 0..fields_length {
+            fields.push(ReferenceTypeField::read_options(
+                reader,
+                endian,
+                args,
+                with_generic,
+            )?);
+        }
+        Ok(fields)
+    }
+}
+
+#[derive(Debug)]
+pub struct FieldsReply {
+    pub fields: Vec<ReferenceTypeField>,
+}
+impl BinRead for FieldsReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(FieldsReply {
+            fields: ReferenceTypeField::read_list(reader, endian, args, false)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FieldsWithGenericReply {
+    pub fields: Vec<ReferenceTypeField>,
+}
+impl BinRead for FieldsWithGenericReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(FieldsWithGenericReply {
+            fields: ReferenceTypeField::read_list(reader, endian, args, true)?,
+        })
+    }
+}
+
+/// A method declared by a reference type. The descriptor is parsed the same way as in
+/// locally read class files.
+#[derive(Debug)]
+pub struct ReferenceTypeMethod {
+    pub method_id: MethodId,
+    pub name: String,
+    pub descriptor: MethodDescriptor,
+    pub generic_signature: Option<String>,
+    pub access_flags: MethodAccessFlags,
+}
+impl ReferenceTypeMethod {
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+        with_generic: bool,
+    ) -> binrw::BinResult<Self> {
+        let method_id = MethodId::read_options(reader, endian, args)?;
+        let name = JdwpString::read_options(reader, endian, ())?;
+        let signature_pos = reader.stream_position()?;
+        let signature = JdwpString::read_options(reader, endian, ())?;
+        let descriptor =
+            parse_method_descriptor(&signature.string).map_err(|e| binrw::Error::Custom {
+                pos: signature_pos,
+                err: Box::new(format!(
+                    "Invalid method descriptor {}: {:?}",
+                    signature.string, e
+                )),
+            })?;
+        let generic_signature = if with_generic {
+            read_generic_signature(reader, endian)?
+        } else {
+            None
+        };
+        let mod_bits = i32::read_options(reader, endian, ())?;
+
+        Ok(ReferenceTypeMethod {
+            method_id,
+            name: name.string,
+            descriptor,
+            generic_signature,
+            access_flags: MethodAccessFlags::from_bits_retain(mod_bits as u16),
+        })
+    }
+
+    fn read_list<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+        with_generic: bool,
+    ) -> binrw::BinResult<Vec<Self>> {
+        let methods_length = read_count(reader, endian)?;
+        let mut methods = vec_for_count(methods_length);
+        for _ in 0..methods_length {
+            methods.push(ReferenceTypeMethod::read_options(
+                reader,
+                endian,
+                args,
+                with_generic,
+            )?);
+        }
+        Ok(methods)
+    }
+}
+
+#[derive(Debug)]
+pub struct MethodsReply {
+    pub methods: Vec<ReferenceTypeMethod>,
+}
+impl BinRead for MethodsReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(MethodsReply {
+            methods: ReferenceTypeMethod::read_list(reader, endian, args, false)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MethodsWithGenericReply {
+    pub methods: Vec<ReferenceTypeMethod>,
+}
+impl BinRead for MethodsWithGenericReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(MethodsWithGenericReply {
+            methods: ReferenceTypeMethod::read_list(reader, endian, args, true)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct InterfacesReply {
+    pub interfaces: Vec<ReferenceTypeId>,
+}
+impl BinRead for InterfacesReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let interfaces_length = read_count(reader, endian)?;
+        let mut interfaces = vec_for_count(interfaces_length);
+        for _ in 0..interfaces_length {
+            interfaces.push(ReferenceTypeId::read_options(reader, endian, args)?);
+        }
+
+        Ok(InterfacesReply { interfaces })
+    }
+}
+
+/// Body of the Method commands which only take the method.
+#[derive(Debug)]
+pub struct MethodRequest {
+    pub ref_type: ReferenceTypeId,
+    pub method_id: MethodId,
+}
+impl BinWrite for MethodRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.ref_type.write_options(writer, endian, args)?;
+        self.method_id.write_options(writer, endian, args)
+    }
+}
+impl BinRead for MethodRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(MethodRequest {
+            ref_type: ReferenceTypeId::read_options(reader, endian, args)?,
+            method_id: MethodId::read_options(reader, endian, args)?,
+        })
+    }
+}
+
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Clone, Copy)]
+pub struct LineTableEntry {
+    pub line_code_index: u64,
+    pub line_number: i32,
+}
+
+#[binrw]
+#[brw(big)]
+#[derive(Debug)]
+pub struct LineTableReply {
+    /// Lowest valid code index of the method, or -1 for native methods.
+    pub start: i64,
+    /// Highest valid code index of the method, or -1 for native methods.
+    pub end: i64,
+    #[bw(calc = lines.len() as i32)]
+    lines_length: i32,
+    #[br(count = lines_length)]
+    pub lines: Vec<LineTableEntry>,
+}
+impl LineTableReply {
+    /// Returns the code indices at which the given source line starts.
+    pub fn code_indices_for_line(&self, line_number: i32) -> Vec<u64> {
+        self.lines
+            .iter()
+            .filter(|entry| entry.line_number == line_number)
+            .map(|entry| entry.line_code_index)
+            .collect()
+    }
+
+    /// Returns the source line containing the given code index.
+    pub fn line_for_code_index(&self, code_index: u64) -> Option<i32> {
+        self.lines
+            .iter()
+            .filter(|entry| entry.line_code_index <= code_index)
+            .max_by_key(|entry| entry.line_code_index)
+            .map(|entry| entry.line_number)
+    }
+}
+
+/// A local variable of a method, visible in `length` bytes of code starting at `code_index`.
+#[derive(Debug)]
+pub struct MethodVariable {
+    pub code_index: u64,
+    pub name: String,
+    pub descriptor: FieldDescriptor,
+    pub generic_signature: Option<String>,
+    pub length: i32,
+    pub slot: i32,
+}
+impl MethodVariable {
+    /// Whether the variable is in scope at the given code index.
+    pub fn is_visible_at(&self, code_index: u64) -> bool {
+        code_index >= self.code_index && code_index < self.code_index + self.length as u64
+    }
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        with_generic: bool,
+    ) -> binrw::BinResult<Self> {
+        let code_index = u64::read_options(reader, endian, ())?;
+        let name = JdwpString::read_options(reader, endian, ())?;
+        let signature_pos = reader.stream_position()?;
+        let signature = JdwpString::read_options(reader, endian, ())?;
+        let descriptor =
+            parse_field_descriptor(&signature.string).map_err(|e| binrw::Error::Custom {
+                pos: signature_pos,
+                err: Box::new(format!(
+                    "Invalid variable descriptor {}: {:?}",
+                    signature.string, e
+                )),
+            })?;
+        let generic_signature = if with_generic {
+            read_generic_signature(reader, endian)?
+        } else {
+            None
+        };
+
+        Ok(MethodVariable {
+            code_index,
+            name: name.string,
+            descriptor,
+            generic_signature,
+            length: i32::read_options(reader, endian, ())?,
+            slot: i32::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct VariableTableReply {
+    /// Number of words in the frame used by the arguments, including `this`.
+    pub arg_count: i32,
+    pub variables: Vec<MethodVariable>,
+}
+impl VariableTableReply {
+    fn read_with_generic<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        with_generic: bool,
+    ) -> binrw::BinResult<Self> {
+        let arg_count = i32::read_options(reader, endian, ())?;
+        let variables_length = read_count(reader, endian)?;
+        let mut variables = vec_for_count(variables_length);
+        for _ in 0..variables_length {
+            variables.push(MethodVariable::read_options(reader, endian, with_generic)?);
+        }
+
+        Ok(VariableTableReply {
+            arg_count,
+            variables,
+        })
+    }
+}
+impl BinRead for VariableTableReply {
+    type Args<'a> = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        _: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        VariableTableReply::read_with_generic(reader, endian, false)
+    }
+}
+
+#[derive(Debug)]
+pub struct VariableTableWithGenericReply(pub VariableTableReply);
+impl BinRead for VariableTableWithGenericReply {
+    type Args<'a> = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        _: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(VariableTableWithGenericReply(
+            VariableTableReply::read_with_generic(reader, endian, true)?,
+        ))
+    }
+}
+
+#[binrw]
+#[brw(big)]
+#[derive(Debug)]
+pub struct BytecodesReply {
+    #[bw(calc = bytes.len() as i32)]
+    bytes_length: i32,
+    #[br(count = bytes_length)]
+    pub bytes: Vec<u8>,
+}
+impl BytecodesReply {
+    pub fn parse_instructions(&self) -> binrw::BinResult<Vec<Instruction>> {
+        let mut cursor = std::io::Cursor::new(&self.bytes);
+        crate::bytecode::parse_instructions(&mut cursor)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectReferenceTypeReply {
+    pub ref_type_tag: TypeTag,
+    pub type_id: ReferenceTypeId,
+}
+impl BinRead for ObjectReferenceTypeReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ObjectReferenceTypeReply {
+            ref_type_tag: TypeTag::read_options(reader, endian, ())?,
+            type_id: ReferenceTypeId::read_options(reader, endian, args)?,
+        })
+    }
+}
+
+fn read_fields<R: std::io::Read + std::io::Seek>(
+    reader: &mut R,
+    endian: binrw::Endian,
+    args: JdwpIdSizes,
+) -> binrw::BinResult<Vec<FieldId>> {
+    let fields_length = read_count(reader, endian)?;
+    let mut fields = vec_for_count(fields_length);
+    for _ in 0..fields_length {
+        fields.push(FieldId::read_options(reader, endian, args)?);
+    }
+    Ok(fields)
+}
+
+/// Body of ReferenceType.GetValues, which reads static fields.
+#[derive(Debug)]
+pub struct ReferenceTypeGetValuesRequest {
+    pub ref_type: ReferenceTypeId,
+    pub fields: Vec<FieldId>,
+}
+impl BinWrite for ReferenceTypeGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.ref_type.write_options(writer, endian, args)?;
+        (self.fields.len() as i32).write_options(writer, endian, ())?;
+        for field in self.fields.iter() {
+            field.write_options(writer, endian, args)?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for ReferenceTypeGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let ref_type = ReferenceTypeId::read_options(reader, endian, args)?;
+        let fields = read_fields(reader, endian, args)?;
+
+        Ok(ReferenceTypeGetValuesRequest { ref_type, fields })
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectGetValuesRequest {
+    pub object: ObjectId,
+    pub fields: Vec<FieldId>,
+}
+impl BinWrite for ObjectGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.object.write_options(writer, endian, args)?;
+        (self.fields.len() as i32).write_options(writer, endian, ())?;
+        for field in self.fields.iter() {
+            field.write_options(writer, endian, args)?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for ObjectGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let object = ObjectId::read_options(reader, endian, args)?;
+        let fields = read_fields(reader, endian, args)?;
+
+        Ok(ObjectGetValuesRequest { object, fields })
+    }
+}
+
+/// A list of tagged values, as returned by the GetValues commands.
+#[derive(Debug)]
+pub struct ValuesReply {
+    pub values: Vec<Value>,
+}
+impl BinRead for ValuesReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let values_length = read_count(reader, endian)?;
+        let mut values = vec_for_count(values_length);
+        for _ in 0..values_length {
+            values.push(Value::read_options(reader, endian, args)?);
+        }
+
+        Ok(ValuesReply { values })
+    }
+}
+
+/// A new value for a field. Values are written untagged, so they must match the
+/// field's type exactly.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldValue {
+    pub field_id: FieldId,
+    pub value: Value,
+}
+
+#[derive(Debug)]
+pub struct ObjectSetValuesRequest {
+    pub object: ObjectId,
+    pub values: Vec<FieldValue>,
+}
+impl BinWrite for ObjectSetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.object.write_options(writer, endian, args)?;
+        (self.values.len() as i32).write_options(writer, endian, ())?;
+        for field_value in self.values.iter() {
+            field_value.field_id.write_options(writer, endian, args)?;
+            field_value.value.write_untagged(writer, endian, args)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ArrayGetValuesRequest {
+    pub array: ObjectId,
+    pub first_index: i32,
+    pub length: i32,
+}
+impl BinWrite for ArrayGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.array.write_options(writer, endian, args)?;
+        self.first_index.write_options(writer, endian, ())?;
+        self.length.write_options(writer, endian, ())
+    }
+}
+impl BinRead for ArrayGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ArrayGetValuesRequest {
+            array: ObjectId::read_options(reader, endian, args)?,
+            first_index: i32::read_options(reader, endian, ())?,
+            length: i32::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+/// A slice of an array. Primitive components are sent untagged, object components
+/// carry their own tag.
+#[derive(Debug)]
+pub struct ArrayRegion {
+    pub tag: Tag,
+    pub values: Vec<Value>,
+}
+impl BinRead for ArrayRegion {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let tag = Tag::read_options(reader, endian, ())?;
+        let values_length = read_count(reader, endian)?;
+        let mut values = vec_for_count(values_length);
+        for _ in 0..values_length {
+            if tag.is_primitive() {
+                values.push(Value::read_untagged(reader, endian, tag, args)?);
+            } else {
+                values.push(Value::read_options(reader, endian, args)?);
+            }
+        }
+
+        Ok(ArrayRegion { tag, values })
+    }
+}
+
+#[derive(Debug)]
+pub struct ArraySetValuesRequest {
+    pub array: ObjectId,
+    pub first_index: i32,
+    pub values: Vec<Value>,
+}
+impl BinWrite for ArraySetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.array.write_options(writer, endian, args)?;
+        self.first_index.write_options(writer, endian, ())?;
+        (self.values.len() as i32).write_options(writer, endian, ())?;
+        for value in self.values.iter() {
+            value.write_untagged(writer, endian, args)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_arguments<R: std::io::Read + std::io::Seek>(
+    reader: &mut R,
+    endian: binrw::Endian,
+    args: JdwpIdSizes,
+) -> binrw::BinResult<Vec<Value>> {
+    let arguments_length = read_count(reader, endian)?;
+    let mut arguments = vec_for_count(arguments_length);
+    for _ in 0..arguments_length {
+        arguments.push(Value::read_options(reader, endian, args)?);
+    }
+    Ok(arguments)
+}
+
+fn write_arguments<W: std::io::Write + std::io::Seek>(
+    arguments: &[Value],
+    writer: &mut W,
+    endian: binrw::Endian,
+    args: JdwpIdSizes,
+) -> binrw::BinResult<()> {
+    (arguments.len() as i32).write_options(writer, endian, ())?;
+    for argument in arguments.iter() {
+        argument.write_options(writer, endian, args)?;
+    }
+    Ok(())
+}
+
+/// Body of ClassType.InvokeMethod, ClassType.NewInstance and InterfaceType.InvokeMethod.
+#[derive(Debug)]
+pub struct StaticInvokeRequest {
+    pub class: ReferenceTypeId,
+    pub thread: ThreadId,
+    pub method_id: MethodId,
+    pub arguments: Vec<Value>,
+    pub options: InvokeOptions,
+}
+impl BinWrite for StaticInvokeRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.class.write_options(writer, endian, args)?;
+        self.thread.write_options(writer, endian, args)?;
+        self.method_id.write_options(writer, endian, args)?;
+        write_arguments(&self.arguments, writer, endian, args)?;
+        self.options.write_options(writer, endian, ())
+    }
+}
+impl BinRead for StaticInvokeRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(StaticInvokeRequest {
+            class: ReferenceTypeId::read_options(reader, endian, args)?,
+            thread: ThreadId::read_options(reader, endian, args)?,
+            method_id: MethodId::read_options(reader, endian, args)?,
+            arguments: read_arguments(reader, endian, args)?,
+            options: InvokeOptions::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectInvokeRequest {
+    pub object: ObjectId,
+    pub thread: ThreadId,
+    pub class: ReferenceTypeId,
+    pub method_id: MethodId,
+    pub arguments: Vec<Value>,
+    pub options: InvokeOptions,
+}
+impl BinWrite for ObjectInvokeRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.object.write_options(writer, endian, args)?;
+        self.thread.write_options(writer, endian, args)?;
+        self.class.write_options(writer, endian, args)?;
+        self.method_id.write_options(writer, endian, args)?;
+        write_arguments(&self.arguments, writer, endian, args)?;
+        self.options.write_options(writer, endian, ())
+    }
+}
+impl BinRead for ObjectInvokeRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ObjectInvokeRequest {
+            object: ObjectId::read_options(reader, endian, args)?,
+            thread: ThreadId::read_options(reader, endian, args)?,
+            class: ReferenceTypeId::read_options(reader, endian, args)?,
+            method_id: MethodId::read_options(reader, endian, args)?,
+            arguments: read_arguments(reader, endian, args)?,
+            options: InvokeOptions::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+/// Outcome of a method invocation. If the method threw, `exception` holds the thrown
+/// object and `return_value` should be ignored.
+#[derive(Debug)]
+pub struct InvokeMethodReply {
+    pub return_value: Value,
+    pub exception: Option<TaggedObjectId>,
+}
+impl BinRead for InvokeMethodReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(InvokeMethodReply {
+            return_value: Value::read_options(reader, endian, args)?,
+            exception: TaggedObjectId::read_optional(reader, endian, args)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct NewInstanceReply {
+    pub new_object: Option<TaggedObjectId>,
+    pub exception: Option<TaggedObjectId>,
+}
+impl BinRead for NewInstanceReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(NewInstanceReply {
+            new_object: TaggedObjectId::read_optional(reader, endian, args)?,
+            exception: TaggedObjectId::read_optional(reader, endian, args)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AllThreadsReply {
+    pub threads: Vec<ThreadId>,
+}
+impl BinRead for AllThreadsReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let threads_length = read_count(reader, endian)?;
+        let mut threads = vec_for_count(threads_length);
+        for _ in 0..threads_length {
+            threads.push(ThreadId::read_options(reader, endian, args)?);
+        }
+
+        Ok(AllThreadsReply { threads })
+    }
+}
+
+#[derive(Debug)]
+pub struct TopLevelThreadGroupsReply {
+    pub groups: Vec<ThreadGroupId>,
+}
+impl BinRead for TopLevelThreadGroupsReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let groups_length = read_count(reader, endian)?;
+        let mut groups = vec_for_count(groups_length);
+        for _ in 0..groups_length {
+            groups.push(ThreadGroupId::read_options(reader, endian, args)?);
+        }
+
+        Ok(TopLevelThreadGroupsReply { groups })
+    }
+}
+
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadStatusReply {
+    pub thread_status: ThreadStatus,
+    pub suspend_status: SuspendStatus,
+}
+
+#[derive(Debug)]
+pub struct ThreadFramesRequest {
+    pub thread: ThreadId,
+    pub start_frame: i32,
+    /// Number of frames to retrieve, or -1 for all remaining frames.
+    pub length: i32,
+}
+impl BinWrite for ThreadFramesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.thread.write_options(writer, endian, args)?;
+        self.start_frame.write_options(writer, endian, ())?;
+        self.length.write_options(writer, endian, ())
+    }
+}
+impl BinRead for ThreadFramesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ThreadFramesRequest {
+            thread: ThreadId::read_options(reader, endian, args)?,
+            start_frame: i32::read_options(reader, endian, ())?,
+            length: i32::read_options(reader, endian, ())?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ThreadFrame {
+    pub frame_id: FrameId,
+    pub location: Location,
+}
+impl BinRead for ThreadFrame {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ThreadFrame {
+            frame_id: FrameId::read_options(reader, endian, args)?,
+            location: Location::read_options(reader, endian, args)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ThreadFramesReply {
+    pub frames: Vec<ThreadFrame>,
+}
+impl BinRead for ThreadFramesReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let frames_length = read_count(reader, endian)?;
+        let mut frames = vec_for_count(frames_length);
+        for _ in 0..frames_length {
+            frames.push(ThreadFrame::read_options(reader, endian, args)?);
+        }
+
+        Ok(ThreadFramesReply { frames })
+    }
+}
+
+/// A local variable slot to retrieve, along with the tag of its type.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameSlot {
+    pub slot: i32,
+    pub sig_byte: Tag,
+}
+
+#[derive(Debug)]
+pub struct StackFrameGetValuesRequest {
+    pub thread: ThreadId,
+    pub frame: FrameId,
+    pub slots: Vec<FrameSlot>,
+}
+impl BinWrite for StackFrameGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.thread.write_options(writer, endian, args)?;
+        self.frame.write_options(writer, endian, args)?;
+        (self.slots.len() as i32).write_options(writer, endian, ())?;
+        for slot in self.slots.iter() {
+            slot.slot.write_options(writer, endian, ())?;
+            slot.sig_byte.write_options(writer, endian, ())?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for StackFrameGetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let thread = ThreadId::read_options(reader, endian, args)?;
+        let frame = FrameId::read_options(reader, endian, args)?;
+        let slots_length = read_count(reader, endian)?;
+        let mut slots = vec_for_count(slots_length);
+        for _ in 0..slots_length {
+            slots.push(FrameSlot {
+                slot: i32::read_options(reader, endian, ())?,
+                sig_byte: Tag::read_options(reader, endian, ())?,
+            });
+        }
+
+        Ok(StackFrameGetValuesRequest {
+            thread,
+            frame,
+            slots,
+        })
+    }
+}
+
+/// A new value for a local variable slot.
+#[derive(Debug, Clone, Copy)]
+pub struct SlotValue {
+    pub slot: i32,
+    pub value: Value,
+}
+
+#[derive(Debug)]
+pub struct StackFrameSetValuesRequest {
+    pub thread: ThreadId,
+    pub frame: FrameId,
+    pub slot_values: Vec<SlotValue>,
+}
+impl BinWrite for StackFrameSetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.thread.write_options(writer, endian, args)?;
+        self.frame.write_options(writer, endian, args)?;
+        (self.slot_values.len() as i32).write_options(writer, endian, ())?;
+        for slot_value in self.slot_values.iter() {
+            slot_value.slot.write_options(writer, endian, ())?;
+            slot_value.value.write_options(writer, endian, args)?;
+        }
+        Ok(())
+    }
+}
+impl BinRead for StackFrameSetValuesRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let thread = ThreadId::read_options(reader, endian, args)?;
+        let frame = FrameId::read_options(reader, endian, args)?;
+        let slot_values_length = read_count(reader, endian)?;
+        let mut slot_values = vec_for_count(slot_values_length);
+        for _ in 0..slot_values_length {
+            slot_values.push(SlotValue {
+                slot: i32::read_options(reader, endian, ())?,
+                value: Value::read_options(reader, endian, args)?,
+            });
+        }
+
+        Ok(StackFrameSetValuesRequest {
+            thread,
+            frame,
+            slot_values,
+        })
+    }
+}
+
+/// Body of the StackFrame commands which only take the frame.
+#[derive(Debug)]
+pub struct StackFrameRequest {
+    pub thread: ThreadId,
+    pub frame: FrameId,
+}
+impl BinWrite for StackFrameRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.thread.write_options(writer, endian, args)?;
+        self.frame.write_options(writer, endian, args)
+    }
+}
+impl BinRead for StackFrameRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(StackFrameRequest {
+            thread: ThreadId::read_options(reader, endian, args)?,
+            frame: FrameId::read_options(reader, endian, args)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MonitorInfoReply {
+    /// The thread owning the monitor, if any.
+    pub owner: Option<ThreadId>,
+    pub entry_count: i32,
+    /// Threads waiting in `Object.wait` for the monitor to be notified.
+    pub waiters: Vec<ThreadId>,
+}
+impl BinRead for MonitorInfoReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let owner = ThreadId::read_options(reader, endian, args)?;
+        let entry_count = i32::read_options(reader, endian, ())?;
+        let waiters_length = read_count(reader, endian)?;
+        let mut waiters = vec_for_count(waiters_length);
+        for _ in 0..waiters_length {
+            waiters.push(ThreadId::read_options(reader, endian, args)?);
+        }
+
+        Ok(MonitorInfoReply {
+            owner: (!owner.is_null()).then_some(owner),
+            entry_count,
+            waiters,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct OwnedMonitorsReply {
+    pub monitors: Vec<TaggedObjectId>,
+}
+impl BinRead for OwnedMonitorsReply {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let monitors_length = read_count(reader, endian)?;
+        let mut monitors = vec_for_count(monitors_length);
+        for _ in 0..monitors_length {
+            monitors.push(TaggedObjectId::read_options(reader, endian, args)?);
+        }
+
+        Ok(OwnedMonitorsReply { monitors })
+    }
+}
+
+#[derive(Debug)]
+pub struct ForceEarlyReturnRequest {
+    pub thread: ThreadId,
+    pub value: Value,
+}
+impl BinWrite for ForceEarlyReturnRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.thread.write_options(writer, endian, args)?;
+        self.value.write_options(writer, endian, args)
+    }
+}
+impl BinRead for ForceEarlyReturnRequest {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(ForceEarlyReturnRequest {
+            thread: ThreadId::read_options(reader, endian, args)?,
+            value: Value::read_options(reader, endian, args)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::descriptors::{FieldDescriptor, MethodDescriptor, Type};
+    use crate::java_class_file::MethodAccessFlags;
+    use crate::jdwp::{
+        ArrayRegion, BytecodesReply, Capabilities, CapabilitiesReply, ClassDefinition, FieldId,
+        FieldsReply, InvokeMethodReply, InvokeOptions, LineTableReply, MethodId,
+        MethodsWithGenericReply, MonitorInfoReply, ObjectId, ObjectInvokeRequest,
+        RedefineClassesRequest, ReferenceTypeId, Value,
+    };
+    use crate::jdwp::{
+        Command, EventKind, EventModifier, EventRequestSet, JdwpIdSizes, Location, StepDepth,
+        StepSize, SuspendPolicy, SuspendStatus, ThreadFramesReply, ThreadId, ThreadStatus,
+        ThreadStatusReply, TypeTag, VariableLengthId,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    const SIZES: JdwpIdSizes = JdwpIdSizes {
+        field_id_size: 8,
+        method_id_size: 8,
+        object_id_size: 8,
+        reference_type_id_size: 8,
+        frame_id_size: 8,
+    };
+
+    #[test]
+    fn test_deserialize_vm_version_command() {
+        let data = [1u8, 1u8];
+        let mut cursor = Cursor::new(&data);
+        let value = Command::read_be(&mut cursor).unwrap();
+        assert_eq!(value, Command::VirtualMachineVersion);
+    }
+
+    #[test]
+    fn test_serialize_breakpoint_request() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 4,
+            object_id_size: 8,
+            reference_type_id_size: 2,
+            frame_id_size: 8,
+        };
+        let request = EventRequestSet {
+            event_kind: EventKind::Breakpoint,
+            suspend_policy: SuspendPolicy::All,
+            modifiers: vec![EventModifier::LocationOnly(Location {
+                type_tag: TypeTag::Class,
+                class_id: ReferenceTypeId(VariableLengthId { value: 0x0102 }),
+                method_id: MethodId(VariableLengthId { value: 0x03040506 }),
+                index: 7,
+            })],
+        };
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        request.write_be_args(&mut cursor, sizes).unwrap();
+
+        let expected = [
+            2u8, 2, 0, 0, 0, 1, // kind, suspend policy, 1 modifier
+            7, 1, 1, 2, 3, 4, 5, 6, // LocationOnly, class tag, class id, method id
+            0, 0, 0, 0, 0, 0, 0, 7, // code index
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_serialize_step_request() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 4,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let request = EventRequestSet {
+            event_kind: EventKind::SingleStep,
+            suspend_policy: SuspendPolicy::EventThread,
+            modifiers: vec![
+                EventModifier::Step {
+                    thread: ThreadId(VariableLengthId { value: 0x0a0b0c0d }),
+                    size: StepSize::Line,
+                    depth: StepDepth::Over,
+                },
+                EventModifier::Count(1),
+            ],
+        };
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        request.write_be_args(&mut cursor, sizes).unwrap();
+
+        let expected = [
+            1u8, 1, 0, 0, 0, 2, // kind, suspend policy, 2 modifiers
+            10, 0x0a, 0x0b, 0x0c, 0x0d, // Step, thread
+            0, 0, 0, 1, 0, 0, 0, 1, // size, depth
+            1, 0, 0, 0, 1, // Count
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_deserialize_thread_status_reply() {
+        let data = [0u8, 0, 0, 4, 0, 0, 0, 1]; // WAIT, suspended
+        let mut cursor = Cursor::new(&data);
+        let value = ThreadStatusReply::read_be(&mut cursor).unwrap();
+        assert_eq!(value.thread_status, ThreadStatus::Wait);
+        assert!(value.suspend_status.contains(SuspendStatus::SUSPENDED));
+    }
+
+    #[test]
+    fn test_deserialize_thread_frames_reply() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 8,
+            reference_type_id_size: 8,
+            frame_id_size: 4,
+        };
+        let mut data: Vec<u8> = vec![0, 0, 0, 1]; // 1 frame
+        data.extend([0, 0, 0, 9]); // frame id
+        data.push(1); // class
+        data.extend(2u64.to_be_bytes());
+        data.extend(3u64.to_be_bytes());
+        data.extend(4u64.to_be_bytes());
+
+        let mut cursor = Cursor::new(&data);
+        let value = ThreadFramesReply::read_be_args(&mut cursor, sizes).unwrap();
+        assert_eq!(value.frames.len(), 1);
+        assert_eq!(value.frames[0].frame_id.0.value, 9);
+        assert_eq!(value.frames[0].location.index, 4);
+    }
+
+    #[test]
+    fn test_deserialize_methods_with_generic_reply() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 4,
+            object_id_size: 8,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let mut data: Vec<u8> = vec![0, 0, 0, 1]; // 1 method
+        data.extend([0, 0, 0, 5]); // method id
+        for string in ["get", "(I)Ljava/lang/Object;", "(I)TT;"] {
+            data.extend((string.len() as u32).to_be_bytes());
+            data.extend(string.as_bytes());
+        }
+        data.extend([0, 0, 0, 1]); // public
+
+        let mut cursor = Cursor::new(&data);
+        let value = MethodsWithGenericReply::read_be_args(&mut cursor, sizes).unwrap();
+        let method = &value.methods[0];
+        assert_eq!(method.method_id.0.value, 5);
+        assert_eq!(method.name, "get");
+        assert_eq!(
+            method.descriptor,
+            MethodDescriptor::new(
+                vec![FieldDescriptor::from_type(Type::Integer)],
+                Some(FieldDescriptor::from_class_str("java/lang/Object"))
+            )
+        );
+        assert_eq!(method.generic_signature.as_deref(), Some("(I)TT;"));
+        assert!(method.access_flags.contains(MethodAccessFlags::PUBLIC));
+    }
+
+    #[test]
+    fn test_deserialize_fields_reply_invalid_descriptor() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 8,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let mut data: Vec<u8> = vec![0, 0, 0, 1]; // 1 field
+        data.extend(1u64.to_be_bytes());
+        for string in ["x", "Q"] {
+            data.extend((string.len() as u32).to_be_bytes());
+            data.extend(string.as_bytes());
+        }
+        data.extend([0, 0, 0, 0]);
+
+        let mut cursor = Cursor::new(&data);
+        assert!(FieldsReply::read_be_args(&mut cursor, sizes).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_line_table_reply() {
+        let mut data: Vec<u8> = vec![];
+        data.extend(0i64.to_be_bytes()); // start
+        data.extend(20i64.to_be_bytes()); // end
+        data.extend(3i32.to_be_bytes());
+        for (index, line) in [(0u64, 10i32), (4, 11), (12, 10)] {
+            data.extend(index.to_be_bytes());
+            data.extend(line.to_be_bytes());
+        }
+
+        let mut cursor = Cursor::new(&data);
+        let value = LineTableReply::read_be(&mut cursor).unwrap();
+        assert_eq!(value.end, 20);
+        assert_eq!(value.code_indices_for_line(10), vec![0, 12]);
+        assert_eq!(value.line_for_code_index(7), Some(11));
+    }
+
+    #[test]
+    fn test_deserialize_bytecodes_reply() {
+        let data = [0u8, 0, 0, 2, 0x03, 0xac]; // iconst_0, ireturn
+        let mut cursor = Cursor::new(&data);
+        let value = BytecodesReply::read_be(&mut cursor).unwrap();
+        assert_eq!(value.bytes, vec![0x03, 0xac]);
+        assert_eq!(value.parse_instructions().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_deserialize_primitive_array_region() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 8,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let data = [b'S', 0, 0, 0, 2, 0, 1, 0xff, 0xff]; // short[] { 1, -1 }
+        let mut cursor = Cursor::new(&data);
+        let value = ArrayRegion::read_be_args(&mut cursor, sizes).unwrap();
+        assert_eq!(value.values, vec![Value::Short(1), Value::Short(-1)]);
+    }
+
+    #[test]
+    fn test_deserialize_object_array_region() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 2,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let data = [b'L', 0, 0, 0, 2, b's', 0, 1, b'L', 0, 0]; // { "...", null }
+        let mut cursor = Cursor::new(&data);
+        let value = ArrayRegion::read_be_args(&mut cursor, sizes).unwrap();
+        assert_eq!(
+            value.values,
+            vec![
+                Value::String(ObjectId(VariableLengthId { value: 1 })),
+                Value::Object(ObjectId(VariableLengthId { value: 0 }))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serialize_object_invoke_request() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 2,
+            object_id_size: 2,
+            reference_type_id_size: 2,
+            frame_id_size: 8,
+        };
+        let request = ObjectInvokeRequest {
+            object: ObjectId(VariableLengthId { value: 1 }),
+            thread: ThreadId(VariableLengthId { value: 2 }),
+            class: ReferenceTypeId(VariableLengthId { value: 3 }),
+            method_id: MethodId(VariableLengthId { value: 4 }),
+            arguments: vec![Value::Int(5)],
+            options: InvokeOptions::SINGLE_THREADED | InvokeOptions::NONVIRTUAL,
+        };
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        request.write_be_args(&mut cursor, sizes).unwrap();
+
+        let expected = [
+            0u8, 1, 0, 2, 0, 3, 0, 4, // object, thread, class, method
+            0, 0, 0, 1, b'I', 0, 0, 0, 5, // 1 argument
+            0, 0, 0, 3, // options
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_deserialize_invoke_reply_with_exception() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 4,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let data = [b'V', b'L', 0, 0, 0, 9];
+        let mut cursor = Cursor::new(&data);
+        let value = InvokeMethodReply::read_be_args(&mut cursor, sizes).unwrap();
+        assert_eq!(value.return_value, Value::Void);
+        assert_eq!(value.exception.unwrap().object_id.0.value, 9);
+    }
+
+    #[test]
+    fn test_serialize_redefine_classes_request() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 8,
+            reference_type_id_size: 4,
+            frame_id_size: 8,
+        };
+        let request = RedefineClassesRequest {
+            classes: vec![ClassDefinition {
+                ref_type: ReferenceTypeId(VariableLengthId { value: 0x11 }),
+                class_bytes: vec![0xca, 0xfe, 0xba, 0xbe],
+            }],
+        };
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        request.write_be_args(&mut cursor, sizes).unwrap();
+
+        let expected = [
+            0u8, 0, 0, 1, // 1 class
+            0, 0, 0, 0x11, // ref type
+            0, 0, 0, 4, 0xca, 0xfe, 0xba, 0xbe, // class file
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_deserialize_reply_with_negative_count() {
+        let data: Vec<u8> = vec![0xff, 0xff, 0xff, 0xfe]; // -2 frames
+
+        let mut cursor = Cursor::new(&data);
+        let error = ThreadFramesReply::read_be_args(&mut cursor, SIZES).unwrap_err();
+        assert!(matches!(error, binrw::Error::AssertFail { pos: 0, .. }));
+    }
+
+    #[test]
+    fn test_deserialize_redefine_classes_request_with_truncated_class() {
+        let data: Vec<u8> = vec![
+            0, 0, 0, 1, // 1 class
+            0, 0, 0, 0, 0, 0, 0, 0x11, // ref type
+            0x7f, 0xff, 0xff, 0xff, 0xca, 0xfe, // class file claiming i32::MAX bytes
+        ];
+
+        let mut cursor = Cursor::new(&data);
+        assert!(RedefineClassesRequest::read_be_args(&mut cursor, SIZES).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_capabilities() {
+        let mut data = [0u8; 32];
+        data[7] = 1; // canRedefineClasses
+        data[20] = 1; // canForceEarlyReturn
+        let mut cursor = Cursor::new(&data);
+        let value = Capabilities::read_be(&mut cursor).unwrap();
+        assert!(value.can_redefine_classes);
+        assert!(value.can_force_early_return);
+        assert!(!value.can_add_method);
+        assert_eq!(cursor.position(), 32);
+    }
+
+    #[test]
+    fn test_serialize_exception_request() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 8,
+            reference_type_id_size: 4,
+            frame_id_size: 8,
+        };
+        let request = EventRequestSet {
+            event_kind: EventKind::Exception,
+            suspend_policy: SuspendPolicy::All,
+            modifiers: vec![
+                EventModifier::ExceptionOnly {
+                    exception: None,
+                    caught: false,
+                    uncaught: true,
+                },
+                EventModifier::ClassExclude("java.*".to_string()),
+            ],
+        };
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        request.write_be_args(&mut cursor, sizes).unwrap();
+
+        let expected = [
+            4u8, 2, 0, 0, 0, 2, // kind, suspend policy, 2 modifiers
+            8, 0, 0, 0, 0, 0, 1, // ExceptionOnly, any type, uncaught only
+            6, 0, 0, 0, 6, b'j', b'a', b'v', b'a', b'.', b'*', // ClassExclude
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_serialize_field_watch_request() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 2,
+            method_id_size: 8,
+            object_id_size: 4,
+            reference_type_id_size: 4,
+            frame_id_size: 8,
+        };
+        let request = EventRequestSet {
+            event_kind: EventKind::FieldModification,
+            suspend_policy: SuspendPolicy::EventThread,
+            modifiers: vec![
+                EventModifier::FieldOnly {
+                    ref_type: ReferenceTypeId(VariableLengthId { value: 3 }),
+                    field: FieldId(VariableLengthId { value: 4 }),
+                },
+                EventModifier::InstanceOnly(ObjectId(VariableLengthId { value: 5 })),
+            ],
+        };
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        request.write_be_args(&mut cursor, sizes).unwrap();
+
+        let expected = [
+            21u8, 1, 0, 0, 0, 2, // kind, suspend policy, 2 modifiers
+            9, 0, 0, 0, 3, 0, 4, // FieldOnly
+            11, 0, 0, 0, 5, // InstanceOnly
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_legacy_capabilities_into_capabilities() {
+        let data = [1u8, 0, 1, 0, 0, 0, 1];
+        let mut cursor = Cursor::new(&data);
+        let value: Capabilities = CapabilitiesReply::read_be(&mut cursor).unwrap().into();
+        assert!(value.can_watch_field_modification);
+        assert!(!value.can_watch_field_access);
+        assert!(value.can_get_bytecodes);
+        assert!(value.can_get_monitor_info);
+        assert!(!value.can_redefine_classes);
+    }
+
+    #[test]
+    fn test_deserialize_monitor_info_without_owner() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 4,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        };
+        let data = [0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 7];
+        let mut cursor = Cursor::new(&data);
+        let value = MonitorInfoReply::read_be_args(&mut cursor, sizes).unwrap();
+        assert!(value.owner.is_none());
+        assert_eq!(value.entry_count, 0);
+        assert_eq!(value.waiters, [ThreadId(VariableLengthId { value: 7 })]);
+    }
+
+    #[test]
+    fn test_variable_length_id_odd_sizes() {
+        let data = [0x01u8, 0x02, 0x03];
+        let mut cursor = Cursor::new(&data);
+        let value = VariableLengthId::read_be_args(&mut cursor, 3).unwrap();
+        assert_eq!(value.value, 0x010203);
+
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        VariableLengthId {
+            value: 0x0a0b0c0d0e0f,
+        }
+        .write_be_args(&mut cursor, 6)
+        .unwrap();
+        assert_eq!(buffer, [0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]);
+
+        let mut cursor = Cursor::new(&data);
+        assert!(VariableLengthId::read_be_args(&mut cursor, 9).is_err());
     }
 }
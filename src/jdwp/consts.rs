@@ -16,10 +16,141 @@ bitflags! {
 
 binrw_enum! {
     #[repr(u8)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum TypeTag {
         Class = 1,
         Interface = 2,
         Array = 3
     }
 }
+
+binrw_enum! {
+    #[repr(u8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum EventKind {
+        SingleStep = 1,
+        Breakpoint = 2,
+        FramePop = 3,
+        Exception = 4,
+        UserDefined = 5,
+        ThreadStart = 6,
+        ThreadDeath = 7,
+        ClassPrepare = 8,
+        ClassUnload = 9,
+        ClassLoad = 10,
+        FieldAccess = 20,
+        FieldModification = 21,
+        ExceptionCatch = 30,
+        MethodEntry = 40,
+        MethodExit = 41,
+        MethodExitWithReturnValue = 42,
+        MonitorContendedEnter = 43,
+        MonitorContendedEntered = 44,
+        MonitorWait = 45,
+        MonitorWaited = 46,
+        VmStart = 90,
+        VmDeath = 99,
+        VmDisconnected = 100,
+    }
+}
+
+binrw_enum! {
+    #[repr(u8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SuspendPolicy {
+        None = 0,
+        EventThread = 1,
+        All = 2,
+    }
+}
+
+binrw_enum! {
+    #[repr(u8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Tag {
+        Array = b'[',
+        Byte = b'B',
+        Char = b'C',
+        Object = b'L',
+        Float = b'F',
+        Double = b'D',
+        Int = b'I',
+        Long = b'J',
+        Short = b'S',
+        Void = b'V',
+        Boolean = b'Z',
+        String = b's',
+        Thread = b't',
+        ThreadGroup = b'g',
+        ClassLoader = b'l',
+        ClassObject = b'c',
+    }
+}
+impl Tag {
+    /// Primitive values are transferred without a tag inside arrays.
+    pub fn is_primitive(&self) -> bool {
+        matches!(
+            self,
+            Tag::Byte
+                | Tag::Char
+                | Tag::Float
+                | Tag::Double
+                | Tag::Int
+                | Tag::Long
+                | Tag::Short
+                | Tag::Boolean
+        )
+    }
+}
+
+binrw_enum! {
+    #[repr(i32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum StepSize {
+        Min = 0,
+        Line = 1,
+    }
+}
+
+binrw_enum! {
+    #[repr(i32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum StepDepth {
+        Into = 0,
+        Over = 1,
+        Out = 2,
+    }
+}
+
+binrw_enum! {
+    #[repr(i32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ThreadStatus {
+        Zombie = 0,
+        Running = 1,
+        Sleeping = 2,
+        Monitor = 3,
+        Wait = 4,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[binrw]
+pub struct SuspendStatus(i32);
+bitflags! {
+    impl SuspendStatus : i32 {
+        const SUSPENDED = 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[binrw]
+pub struct InvokeOptions(i32);
+bitflags! {
+    impl InvokeOptions : i32 {
+        /// Only resume the invoking thread instead of all threads.
+        const SINGLE_THREADED = 1;
+        /// Call the method of the given class, bypassing virtual dispatch.
+        const NONVIRTUAL = 1 << 1;
+    }
+}
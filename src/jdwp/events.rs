@@ -0,0 +1,472 @@
+use binrw::BinRead;
+use std::io::Cursor;
+
+use crate::jdwp::{
+    ClassStatus, EventKind, FieldId, JdwpIdSizes, JdwpString, Location, ReferenceTypeId, RequestId,
+    SuspendPolicy, TaggedObjectId, ThreadId, TypeTag, Value,
+    types::{read_count, vec_for_count},
+};
+
+/// A single event decoded from an `Event.Composite` packet.
+#[derive(Debug)]
+pub enum Event {
+    VmStart {
+        request_id: RequestId,
+        thread: ThreadId,
+    },
+    VmDeath {
+        request_id: RequestId,
+    },
+    SingleStep {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+    },
+    Breakpoint {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+    },
+    MethodEntry {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+    },
+    MethodExit {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+    },
+    MethodExitWithReturnValue {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+        value: Value,
+    },
+    MonitorContendedEnter {
+        request_id: RequestId,
+        thread: ThreadId,
+        object: TaggedObjectId,
+        location: Location,
+    },
+    MonitorContendedEntered {
+        request_id: RequestId,
+        thread: ThreadId,
+        object: TaggedObjectId,
+        location: Location,
+    },
+    MonitorWait {
+        request_id: RequestId,
+        thread: ThreadId,
+        object: TaggedObjectId,
+        location: Location,
+        timeout: i64,
+    },
+    MonitorWaited {
+        request_id: RequestId,
+        thread: ThreadId,
+        object: TaggedObjectId,
+        location: Location,
+        timed_out: bool,
+    },
+    Exception {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+        exception: TaggedObjectId,
+        catch_location: Option<Location>,
+    },
+    ThreadStart {
+        request_id: RequestId,
+        thread: ThreadId,
+    },
+    ThreadDeath {
+        request_id: RequestId,
+        thread: ThreadId,
+    },
+    ClassPrepare {
+        request_id: RequestId,
+        thread: ThreadId,
+        ref_type_tag: TypeTag,
+        type_id: ReferenceTypeId,
+        signature: JdwpString,
+        status: ClassStatus,
+    },
+    ClassUnload {
+        request_id: RequestId,
+        signature: JdwpString,
+    },
+    FieldAccess {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+        ref_type_tag: TypeTag,
+        type_id: ReferenceTypeId,
+        field_id: FieldId,
+        object: TaggedObjectId,
+    },
+    FieldModification {
+        request_id: RequestId,
+        thread: ThreadId,
+        location: Location,
+        ref_type_tag: TypeTag,
+        type_id: ReferenceTypeId,
+        field_id: FieldId,
+        /// The object whose field is modified, with a 0 ID for static fields.
+        object: TaggedObjectId,
+        value_to_be: Value,
+    },
+    /// An event of a kind which cannot be decoded. Event bodies carry no length, so the
+    /// events following it in the same composite are not decoded either.
+    Unknown {
+        kind: u8,
+        request_id: RequestId,
+    },
+}
+impl Event {
+    /// The ID of the event request which caused this event, or 0 for automatically
+    /// generated events.
+    pub fn request_id(&self) -> RequestId {
+        match self {
+            Event::VmStart { request_id, .. }
+            | Event::VmDeath { request_id }
+            | Event::SingleStep { request_id, .. }
+            | Event::Breakpoint { request_id, .. }
+            | Event::MethodEntry { request_id, .. }
+            | Event::MethodExit { request_id, .. }
+            | Event::MethodExitWithReturnValue { request_id, .. }
+            | Event::MonitorContendedEnter { request_id, .. }
+            | Event::MonitorContendedEntered { request_id, .. }
+            | Event::MonitorWait { request_id, .. }
+            | Event::MonitorWaited { request_id, .. }
+            | Event::Exception { request_id, .. }
+            | Event::ThreadStart { request_id, .. }
+            | Event::ThreadDeath { request_id, .. }
+            | Event::ClassPrepare { request_id, .. }
+            | Event::ClassUnload { request_id, .. }
+            | Event::FieldAccess { request_id, .. }
+            | Event::FieldModification { request_id, .. }
+            | Event::Unknown { request_id, .. } => *request_id,
+        }
+    }
+}
+impl BinRead for Event {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let raw_kind = u8::read_options(reader, endian, ())?;
+        let request_id = RequestId::read_options(reader, endian, ())?;
+        let Ok(kind) = EventKind::read_options(&mut Cursor::new([raw_kind]), endian, ()) else {
+            return Ok(Event::Unknown {
+                kind: raw_kind,
+                request_id,
+            });
+        };
+
+        let thread = |reader: &mut R| ThreadId::read_options(reader, endian, args);
+        let location = |reader: &mut R| Location::read_options(reader, endian, args);
+        let object = |reader: &mut R| TaggedObjectId::read_options(reader, endian, args);
+
+        let event = match kind {
+            EventKind::VmStart => Event::VmStart {
+                request_id,
+                thread: thread(reader)?,
+            },
+            EventKind::VmDeath => Event::VmDeath { request_id },
+            EventKind::SingleStep => Event::SingleStep {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+            },
+            EventKind::Breakpoint => Event::Breakpoint {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+            },
+            EventKind::MethodEntry => Event::MethodEntry {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+            },
+            EventKind::MethodExit => Event::MethodExit {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+            },
+            EventKind::MethodExitWithReturnValue => Event::MethodExitWithReturnValue {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+                value: Value::read_options(reader, endian, args)?,
+            },
+            EventKind::MonitorContendedEnter => Event::MonitorContendedEnter {
+                request_id,
+                thread: thread(reader)?,
+                object: object(reader)?,
+                location: location(reader)?,
+            },
+            EventKind::MonitorContendedEntered => Event::MonitorContendedEntered {
+                request_id,
+                thread: thread(reader)?,
+                object: object(reader)?,
+                location: location(reader)?,
+            },
+            EventKind::MonitorWait => Event::MonitorWait {
+                request_id,
+                thread: thread(reader)?,
+                object: object(reader)?,
+                location: location(reader)?,
+                timeout: i64::read_options(reader, endian, ())?,
+            },
+            EventKind::MonitorWaited => Event::MonitorWaited {
+                request_id,
+                thread: thread(reader)?,
+                object: object(reader)?,
+                location: location(reader)?,
+                timed_out: u8::read_options(reader, endian, ())? != 0,
+            },
+            EventKind::Exception => Event::Exception {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+                exception: object(reader)?,
+                catch_location: Location::read_optional(reader, endian, args)?,
+            },
+            EventKind::ThreadStart => Event::ThreadStart {
+                request_id,
+                thread: thread(reader)?,
+            },
+            EventKind::ThreadDeath => Event::ThreadDeath {
+                request_id,
+                thread: thread(reader)?,
+            },
+            EventKind::ClassPrepare => Event::ClassPrepare {
+                request_id,
+                thread: thread(reader)?,
+                ref_type_tag: TypeTag::read_options(reader, endian, ())?,
+                type_id: ReferenceTypeId::read_options(reader, endian, args)?,
+                signature: JdwpString::read_options(reader, endian, ())?,
+                status: ClassStatus::read_options(reader, endian, ())?,
+            },
+            EventKind::ClassUnload => Event::ClassUnload {
+                request_id,
+                signature: JdwpString::read_options(reader, endian, ())?,
+            },
+            EventKind::FieldAccess => Event::FieldAccess {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+                ref_type_tag: TypeTag::read_options(reader, endian, ())?,
+                type_id: ReferenceTypeId::read_options(reader, endian, args)?,
+                field_id: FieldId::read_options(reader, endian, args)?,
+                object: object(reader)?,
+            },
+            EventKind::FieldModification => Event::FieldModification {
+                request_id,
+                thread: thread(reader)?,
+                location: location(reader)?,
+                ref_type_tag: TypeTag::read_options(reader, endian, ())?,
+                type_id: ReferenceTypeId::read_options(reader, endian, args)?,
+                field_id: FieldId::read_options(reader, endian, args)?,
+                object: object(reader)?,
+                value_to_be: Value::read_options(reader, endian, args)?,
+            },
+            // Kinds which only exist for event requests, or are never sent in a composite
+            _ => Event::Unknown {
+                kind: raw_kind,
+                request_id,
+            },
+        };
+
+        Ok(event)
+    }
+}
+
+/// The body of an `Event.Composite` command sent by the VM. Decoding stops after an
+/// `Event::Unknown`, keeping the events read before it.
+#[derive(Debug)]
+pub struct EventComposite {
+    pub suspend_policy: SuspendPolicy,
+    pub events: Vec<Event>,
+}
+impl BinRead for EventComposite {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let suspend_policy = SuspendPolicy::read_options(reader, endian, ())?;
+        let events_length = read_count(reader, endian)?;
+        let mut events = vec_for_count(events_length);
+        for _ in 0..events_length {
+            let event = Event::read_options(reader, endian, args)?;
+            let unknown = matches!(event, Event::Unknown { .. });
+            events.push(event);
+            if unknown {
+                break;
+            }
+        }
+
+        Ok(EventComposite {
+            suspend_policy,
+            events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdwp::VariableLengthId;
+
+    const SIZES: JdwpIdSizes = JdwpIdSizes {
+        field_id_size: 8,
+        method_id_size: 8,
+        object_id_size: 8,
+        reference_type_id_size: 8,
+        frame_id_size: 8,
+    };
+
+    #[test]
+    fn test_read_composite_vm_start_and_breakpoint() {
+        let mut data: Vec<u8> = vec![2, 0, 0, 0, 2]; // suspend all, 2 events
+        data.extend([90, 0, 0, 0, 0]); // VMStart, request 0
+        data.extend(1u64.to_be_bytes()); // thread
+        data.extend([2, 0, 0, 0, 7]); // Breakpoint, request 7
+        data.extend(1u64.to_be_bytes()); // thread
+        data.push(1); // class
+        data.extend(0x10u64.to_be_bytes()); // class id
+        data.extend(0x20u64.to_be_bytes()); // method id
+        data.extend(5u64.to_be_bytes()); // code index
+
+        let mut cursor = Cursor::new(&data);
+        let composite = EventComposite::read_be_args(&mut cursor, SIZES).unwrap();
+        assert_eq!(composite.suspend_policy, SuspendPolicy::All);
+        assert_eq!(composite.events.len(), 2);
+        assert!(matches!(
+            composite.events[0],
+            Event::VmStart {
+                request_id: RequestId(0),
+                thread: ThreadId(VariableLengthId { value: 1 })
+            }
+        ));
+        assert_eq!(composite.events[1].request_id(), RequestId(7));
+        match &composite.events[1] {
+            Event::Breakpoint { location, .. } => {
+                assert_eq!(location.type_tag, TypeTag::Class);
+                assert_eq!(location.class_id.0.value, 0x10);
+                assert_eq!(location.method_id.0.value, 0x20);
+                assert_eq!(location.index, 5);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_uncaught_exception() {
+        let mut data: Vec<u8> = vec![1, 0, 0, 0, 1];
+        data.extend([4, 0, 0, 0, 3]); // Exception, request 3
+        data.extend(1u64.to_be_bytes()); // thread
+        data.push(1);
+        data.extend(0x10u64.to_be_bytes());
+        data.extend(0x20u64.to_be_bytes());
+        data.extend(9u64.to_be_bytes());
+        data.push(b'L');
+        data.extend(0x30u64.to_be_bytes()); // exception object
+        data.extend([0u8; 1 + 8 + 8 + 8]); // no catch location
+
+        let mut cursor = Cursor::new(&data);
+        let composite = EventComposite::read_be_args(&mut cursor, SIZES).unwrap();
+        match &composite.events[0] {
+            Event::Exception {
+                exception,
+                catch_location,
+                ..
+            } => {
+                assert_eq!(exception.object_id.0.value, 0x30);
+                assert!(catch_location.is_none());
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        assert_eq!(cursor.position() as usize, data.len());
+    }
+
+    #[test]
+    fn test_read_field_modification() {
+        let mut data: Vec<u8> = vec![1, 0, 0, 0, 1];
+        data.extend([21, 0, 0, 0, 6]); // FieldModification, request 6
+        data.extend(1u64.to_be_bytes()); // thread
+        data.push(1);
+        data.extend(0x10u64.to_be_bytes());
+        data.extend(0x20u64.to_be_bytes());
+        data.extend(9u64.to_be_bytes());
+        data.push(1); // class
+        data.extend(0x10u64.to_be_bytes()); // type
+        data.extend(0x40u64.to_be_bytes()); // field
+        data.push(b'L');
+        data.extend(0x30u64.to_be_bytes()); // object
+        data.extend([b'I', 0, 0, 0, 42]); // value to be
+
+        let mut cursor = Cursor::new(&data);
+        let composite = EventComposite::read_be_args(&mut cursor, SIZES).unwrap();
+        match &composite.events[0] {
+            Event::FieldModification {
+                field_id,
+                object,
+                value_to_be,
+                ..
+            } => {
+                assert_eq!(field_id.0.value, 0x40);
+                assert_eq!(object.object_id.0.value, 0x30);
+                assert_eq!(*value_to_be, Value::Int(42));
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        assert_eq!(cursor.position() as usize, data.len());
+    }
+
+    #[test]
+    fn test_unknown_event_keeps_preceding_events() {
+        let mut data: Vec<u8> = vec![2, 0, 0, 0, 3]; // suspend all, 3 events
+        data.extend([6, 0, 0, 0, 4]); // ThreadStart, request 4
+        data.extend(1u64.to_be_bytes()); // thread
+        data.extend([5, 0, 0, 0, 8]); // UserDefined, request 8
+        data.extend([0xff; 12]); // body of unknown length
+
+        let mut cursor = Cursor::new(&data);
+        let composite = EventComposite::read_be_args(&mut cursor, SIZES).unwrap();
+        assert_eq!(composite.events.len(), 2);
+        assert!(matches!(composite.events[0], Event::ThreadStart { .. }));
+        assert!(matches!(
+            composite.events[1],
+            Event::Unknown {
+                kind: 5,
+                request_id: RequestId(8)
+            }
+        ));
+    }
+
+    #[test]
+    fn test_composite_with_negative_count_is_rejected() {
+        let data: Vec<u8> = vec![2, 0xff, 0xff, 0xff, 0xff]; // suspend all, -1 events
+
+        let mut cursor = Cursor::new(&data);
+        assert!(EventComposite::read_be_args(&mut cursor, SIZES).is_err());
+    }
+
+    #[test]
+    fn test_composite_with_huge_count_fails_without_allocating() {
+        let mut data: Vec<u8> = vec![2, 0x7f, 0xff, 0xff, 0xff]; // suspend all, i32::MAX events
+        data.extend([90, 0, 0, 0, 0]); // VMStart, request 0
+        data.extend(1u64.to_be_bytes()); // thread
+
+        let mut cursor = Cursor::new(&data);
+        assert!(EventComposite::read_be_args(&mut cursor, SIZES).is_err());
+    }
+}
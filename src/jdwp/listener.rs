@@ -0,0 +1,63 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::time::timeout;
+
+use crate::jdwp::{JdwpClient, result};
+
+/// Accepts connections from VMs started with `-agentlib:jdwp=transport=dt_socket,server=n`,
+/// which connect to the debugger instead of waiting for it.
+///
+/// Which side opened the connection does not matter for the protocol: the debugger still
+/// sends the handshake first and the VM echoes it, so accepted streams are set up exactly
+/// like outbound ones.
+pub struct JdwpListener {
+    listener: TcpListener,
+}
+
+impl JdwpListener {
+    pub async fn bind<A: ToSocketAddrs>(address: A) -> result::Result<Self> {
+        Ok(JdwpListener {
+            listener: TcpListener::bind(address).await?,
+        })
+    }
+
+    /// The address the listener is bound to, e.g. to find out which port was picked
+    /// when binding to port 0.
+    pub fn local_addr(&self) -> result::Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Waits for the next VM to connect and sets up a client for it.
+    pub async fn accept(&self) -> result::Result<(JdwpClient<TcpStream>, SocketAddr)> {
+        let (stream, address) = self.listener.accept().await?;
+        // Don't let a peer which is not a VM block the listener forever
+        let client = timeout(Duration::from_secs(5), JdwpClient::new(stream))
+            .await
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::TimedOut, "VM did not complete the handshake")
+            })??;
+        Ok((client, address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdwp::MockVm;
+
+    #[tokio::test]
+    async fn test_accept_negotiates_session() {
+        let listener = JdwpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let _vm = MockVm::new().spawn(stream);
+
+        let (client, _) = listener.accept().await.unwrap();
+        let session = client.session();
+        assert_eq!(session.id_sizes.object_id_size, 8);
+        assert_eq!(session.version.jdwp_major, 1);
+    }
+}
@@ -0,0 +1,324 @@
+use binrw::{BinRead, BinWrite};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::jdwp::{
+    Capabilities, Command, CommandPacketHeader, JdwpClient, JdwpErrorCode, JdwpIdSizes, JdwpString,
+    REPLY_FLAG, ReplyPacketHeader, result,
+};
+
+/// What the mock VM answers to a command.
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// A successful reply with the given body.
+    Data(Vec<u8>),
+    /// An error reply without a body.
+    Error(JdwpErrorCode),
+}
+
+type Handler = Box<dyn FnMut(&[u8]) -> MockReply + Send>;
+
+/// A scriptable stand-in for the VM side of a JDWP connection, for testing code built on
+/// `JdwpClient` without a JVM.
+///
+/// Each command is answered by its configured handler. Commands without one get a
+/// `NOT_IMPLEMENTED` error, except for the ones `JdwpClient::new` sends, which have
+/// defaults describing a VM with 8 byte IDs and no optional capabilities.
+///
+/// Only built for tests and with the `mock` feature.
+pub struct MockVm {
+    handlers: HashMap<Command, Handler>,
+    events_after: HashMap<Command, Vec<Vec<u8>>>,
+}
+
+/// A command the mock VM received from the client.
+#[derive(Debug, Clone)]
+pub struct ReceivedCommand {
+    pub command: Command,
+    pub data: Vec<u8>,
+}
+
+impl Default for MockVm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockVm {
+    pub fn new() -> Self {
+        let mut version = Vec::new();
+        let mut cursor = Cursor::new(&mut version);
+        (
+            JdwpString {
+                string: "Mock VM".to_string(),
+            },
+            1i32,
+            8i32,
+            JdwpString {
+                string: "1.0".to_string(),
+            },
+            JdwpString {
+                string: "mock".to_string(),
+            },
+        )
+            .write_be(&mut cursor)
+            .expect("version can be written to a buffer");
+
+        MockVm {
+            handlers: HashMap::new(),
+            events_after: HashMap::new(),
+        }
+        .with_id_sizes(JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 8,
+            object_id_size: 8,
+            reference_type_id_size: 8,
+            frame_id_size: 8,
+        })
+        .with_capabilities(Capabilities::default())
+        .reply(Command::VirtualMachineVersion, version)
+    }
+
+    pub fn with_id_sizes(self, sizes: JdwpIdSizes) -> Self {
+        let data = [
+            sizes.field_id_size,
+            sizes.method_id_size,
+            sizes.object_id_size,
+            sizes.reference_type_id_size,
+            sizes.frame_id_size,
+        ]
+        .iter()
+        .flat_map(|size| (*size as i32).to_be_bytes())
+        .collect();
+        self.reply(Command::VirtualMachineIDSizes, data)
+    }
+
+    pub fn with_capabilities(self, capabilities: Capabilities) -> Self {
+        let mut data = Vec::new();
+        capabilities
+            .write_be(&mut Cursor::new(&mut data))
+            .expect("capabilities can be written to a buffer");
+        self.reply(Command::VirtualMachineCapabilitiesNew, data)
+    }
+
+    /// Answers every `command` with `data`.
+    pub fn reply(self, command: Command, data: Vec<u8>) -> Self {
+        self.on(command, move |_| MockReply::Data(data.clone()))
+    }
+
+    /// Answers every `command` with the error `error`.
+    pub fn reply_error(self, command: Command, error: JdwpErrorCode) -> Self {
+        self.on(command, move |_| MockReply::Error(error))
+    }
+
+    /// Answers `command` with whatever `handler` returns for the body of the request.
+    pub fn on<F>(mut self, command: Command, handler: F) -> Self
+    where
+        F: FnMut(&[u8]) -> MockReply + Send + 'static,
+    {
+        self.handlers.insert(command, Box::new(handler));
+        self
+    }
+
+    /// Sends an `Event.Composite` with the body `data` right after replying to the next
+    /// `command`. Several events for the same command are sent one per reply, in order.
+    pub fn push_event_after(mut self, command: Command, data: Vec<u8>) -> Self {
+        self.events_after.entry(command).or_default().push(data);
+        self
+    }
+
+    /// Runs the VM on `stream` in the background, starting with the handshake.
+    pub fn spawn<T>(self, stream: T) -> MockVmHandle
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (packets_tx, packets_rx) = mpsc::unbounded_channel();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let packet_id = Arc::new(AtomicU32::new(0));
+        let disconnect = Arc::new(Notify::new());
+
+        let task = tokio::spawn(self.run(
+            stream,
+            packets_tx.clone(),
+            packets_rx,
+            received.clone(),
+            packet_id.clone(),
+            disconnect.clone(),
+        ));
+        MockVmHandle {
+            packets: packets_tx,
+            received,
+            packet_id,
+            disconnect,
+            task,
+        }
+    }
+
+    /// Connects a client to a new mock VM through an in-memory stream.
+    pub async fn connect(self) -> result::Result<(JdwpClient<DuplexStream>, MockVmHandle)> {
+        let (client_stream, vm_stream) = tokio::io::duplex(64 * 1024);
+        let handle = self.spawn(vm_stream);
+        let client = JdwpClient::new(client_stream).await?;
+        Ok((client, handle))
+    }
+
+    async fn run<T>(
+        mut self,
+        mut stream: T,
+        packets_tx: mpsc::UnboundedSender<Vec<u8>>,
+        mut packets_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+        received: Arc<Mutex<Vec<ReceivedCommand>>>,
+        packet_id: Arc<AtomicU32>,
+        disconnect: Arc<Notify>,
+    ) -> result::Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let mut handshake = [0u8; 14];
+        stream.read_exact(&mut handshake).await?;
+        if &handshake != b"JDWP-Handshake" {
+            return Err(result::Error::ParsingError {
+                message: "Invalid handshake".to_string(),
+            });
+        }
+        stream.write_all(&handshake).await?;
+
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let writer_task = tokio::spawn(async move {
+            while let Some(packet) = packets_rx.recv().await {
+                if writer.write_all(&packet).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let result = loop {
+            let mut header_buffer = vec![0u8; CommandPacketHeader::get_length()];
+            let read = tokio::select! {
+                read = reader.read_exact(&mut header_buffer) => read,
+                _ = disconnect.notified() => break Ok(()),
+            };
+            if let Err(e) = read {
+                // The client hung up
+                break match e.kind() {
+                    std::io::ErrorKind::UnexpectedEof => Ok(()),
+                    _ => Err(e.into()),
+                };
+            }
+            let header =
+                CommandPacketHeader::read_be(&mut Cursor::new(&header_buffer)).map_err(|e| {
+                    result::Error::ParsingError {
+                        message: format!("Parsing error: {:?}", e),
+                    }
+                })?;
+            let mut data = vec![0u8; (header.length as usize).saturating_sub(header_buffer.len())];
+            reader.read_exact(&mut data).await?;
+
+            let reply = match self.handlers.get_mut(&header.command) {
+                Some(handler) => handler(&data),
+                None => MockReply::Error(JdwpErrorCode::NotImplemented),
+            };
+            received.lock().unwrap().push(ReceivedCommand {
+                command: header.command,
+                data,
+            });
+
+            let (error_code, body) = match reply {
+                MockReply::Data(body) => (0, body),
+                MockReply::Error(error) => (error.code(), Vec::new()),
+            };
+            let _ = packets_tx.send(encode_reply(header.id, error_code, &body));
+
+            if let Some(events) = self.events_after.get_mut(&header.command)
+                && !events.is_empty()
+            {
+                let event = events.remove(0);
+                let id = packet_id.fetch_add(1, Ordering::Relaxed);
+                let _ = packets_tx.send(encode_event(id, &event));
+            }
+        };
+
+        writer_task.abort();
+        result
+    }
+}
+
+/// Controls a running mock VM.
+pub struct MockVmHandle {
+    packets: mpsc::UnboundedSender<Vec<u8>>,
+    received: Arc<Mutex<Vec<ReceivedCommand>>>,
+    packet_id: Arc<AtomicU32>,
+    disconnect: Arc<Notify>,
+    task: JoinHandle<result::Result<()>>,
+}
+
+impl MockVmHandle {
+    /// Closes the connection, as if the VM had been killed.
+    pub fn disconnect(&self) {
+        self.disconnect.notify_one();
+    }
+
+    /// Waits until the VM stops, after `disconnect` or once the client hung up. Fails
+    /// with the error that stopped it early, e.g. a failed handshake.
+    pub async fn stopped(self) -> result::Result<()> {
+        match self.task.await {
+            Ok(result) => result,
+            Err(e) => Err(std::io::Error::other(e).into()),
+        }
+    }
+
+    /// Sends an `Event.Composite` command with `data` as its body.
+    pub fn push_event(&self, data: Vec<u8>) {
+        let id = self.packet_id.fetch_add(1, Ordering::Relaxed);
+        let _ = self.packets.send(encode_event(id, &data));
+    }
+
+    /// All commands received so far, including the ones sent while connecting.
+    pub fn received(&self) -> Vec<ReceivedCommand> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Whether `command` has been received at least once.
+    pub fn has_received(&self, command: Command) -> bool {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|received| received.command == command)
+    }
+}
+
+fn encode_reply(id: u32, error_code: u16, data: &[u8]) -> Vec<u8> {
+    let header = ReplyPacketHeader {
+        length: (ReplyPacketHeader::get_length() + data.len()) as u32,
+        id,
+        flags: REPLY_FLAG,
+        error_code,
+    };
+    let mut packet = Vec::new();
+    header
+        .write_be(&mut Cursor::new(&mut packet))
+        .expect("header can be written to a buffer");
+    packet.extend(data);
+    packet
+}
+
+fn encode_event(id: u32, data: &[u8]) -> Vec<u8> {
+    let header = CommandPacketHeader {
+        length: (CommandPacketHeader::get_length() + data.len()) as u32,
+        id,
+        flags: 0,
+        command: Command::EventComposite,
+    };
+    let mut packet = Vec::new();
+    header
+        .write_be(&mut Cursor::new(&mut packet))
+        .expect("header can be written to a buffer");
+    packet.extend(data);
+    packet
+}
@@ -1,11 +1,25 @@
 mod client;
 mod commands;
 mod consts;
+mod events;
+mod listener;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod protocol;
+mod recording;
 mod result;
 mod types;
+mod values;
 
 pub use client::*;
 pub use commands::*;
 pub use consts::*;
+pub use events::*;
+pub use listener::*;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::*;
+pub use protocol::*;
+pub use recording::*;
 pub use result::*;
 pub use types::*;
+pub use values::*;
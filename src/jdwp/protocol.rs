@@ -0,0 +1,346 @@
+use binrw::{BinRead, BinWrite};
+use std::io::Cursor;
+use std::time::Duration;
+
+use crate::jdwp::{
+    AllClassesReply, AllThreadsReply, ArrayGetValuesRequest, ArrayRegion, ArraySetValuesRequest,
+    BytecodesReply, CapabilitiesReply, ClassLoaderId, ClassObjectId, ClassStatus,
+    ClassesBySignatureReply, ClassesBySignatureRequest, Command, DisposeObjectsRequest,
+    EventRequestClear, EventRequestSet, FieldsReply, FieldsWithGenericReply,
+    ForceEarlyReturnRequest, IdSizesReply, InterfacesReply, InvokeMethodReply, JdwpIdSizes,
+    JdwpString, LineTableReply, MethodRequest, MethodsReply, MethodsWithGenericReply,
+    MonitorInfoReply, NewInstanceReply, ObjectGetValuesRequest, ObjectId, ObjectInvokeRequest,
+    ObjectReferenceTypeReply, ObjectSetValuesRequest, OwnedMonitorsReply, RedefineClassesRequest,
+    ReferenceTypeGetValuesRequest, ReferenceTypeId, RequestId, StackFrameGetValuesRequest,
+    StackFrameRequest, StackFrameSetValuesRequest, StaticInvokeRequest, TaggedObjectId,
+    ThreadFramesReply, ThreadFramesRequest, ThreadGroupId, ThreadId, ThreadStatusReply,
+    TopLevelThreadGroupsReply, ValuesReply, VariableTableReply, VariableTableWithGenericReply,
+    VersionReply, result,
+};
+
+/// Invoked methods run arbitrary code in the target VM, so they get more time than
+/// regular commands.
+const INVOKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Arguments of a body type, derived from the ID sizes negotiated with the VM.
+pub trait IdSizesArgs: Sized {
+    /// Returns `None` if the arguments depend on ID sizes which are not known yet.
+    fn from_id_sizes(sizes: Option<JdwpIdSizes>) -> Option<Self>;
+}
+
+/// For bodies without IDs, which can be used before the sizes are negotiated.
+impl IdSizesArgs for () {
+    fn from_id_sizes(_: Option<JdwpIdSizes>) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl IdSizesArgs for JdwpIdSizes {
+    fn from_id_sizes(sizes: Option<JdwpIdSizes>) -> Option<Self> {
+        sizes
+    }
+}
+
+/// A command the debugger sends to the VM, together with the types of its request and
+/// reply bodies. Commands without a body use `()`.
+pub trait JdwpCommand {
+    const COMMAND: Command;
+    /// How long to wait for the reply.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    type Request: for<'a> BinWrite<Args<'a>: IdSizesArgs>;
+    type Reply: for<'a> BinRead<Args<'a>: IdSizesArgs>;
+
+    fn encode_request(
+        request: &Self::Request,
+        sizes: Option<JdwpIdSizes>,
+    ) -> result::Result<Vec<u8>> {
+        let args = IdSizesArgs::from_id_sizes(sizes).ok_or(result::Error::IdSizesUnknown)?;
+        let mut data = Vec::new();
+        request
+            .write_be_args(&mut Cursor::new(&mut data), args)
+            .map_err(|e| result::Error::ParsingError {
+                message: format!("Serialization error: {:?}", e),
+            })?;
+        Ok(data)
+    }
+
+    /// Decodes a request body, e.g. one sent by another debugger. Only available for
+    /// requests which can be read on their own: untagged values need the types of the
+    /// fields or array components they are written to.
+    fn decode_request(data: &[u8], sizes: Option<JdwpIdSizes>) -> result::Result<Self::Request>
+    where
+        Self::Request: for<'a> BinRead<Args<'a>: IdSizesArgs>,
+    {
+        let args = IdSizesArgs::from_id_sizes(sizes).ok_or(result::Error::IdSizesUnknown)?;
+        Self::Request::read_be_args(&mut Cursor::new(data), args).map_err(|e| {
+            result::Error::ParsingError {
+                message: format!("Binary parsing error: {:?}", e),
+            }
+        })
+    }
+
+    fn decode_reply(data: &[u8], sizes: Option<JdwpIdSizes>) -> result::Result<Self::Reply> {
+        let args = IdSizesArgs::from_id_sizes(sizes).ok_or(result::Error::IdSizesUnknown)?;
+        Self::Reply::read_be_args(&mut Cursor::new(data), args).map_err(|e| {
+            result::Error::ParsingError {
+                message: format!("Binary parsing error: {:?}", e),
+            }
+        })
+    }
+}
+
+/// Declares a unit struct implementing `JdwpCommand` for each command.
+macro_rules! jdwp_commands {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident = $command:ident: $request:ty => $reply:ty $(, timeout = $timeout:expr)?;
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy)]
+            pub struct $name;
+
+            impl JdwpCommand for $name {
+                const COMMAND: Command = Command::$command;
+                $(const TIMEOUT: Duration = $timeout;)?
+
+                type Request = $request;
+                type Reply = $reply;
+            }
+        )*
+    };
+}
+
+/// Commands of the VirtualMachine command set.
+pub mod virtual_machine {
+    use super::*;
+
+    jdwp_commands! {
+        Version = VirtualMachineVersion: () => VersionReply;
+        ClassesBySignature = VirtualMachineClassesBySignature:
+            ClassesBySignatureRequest => ClassesBySignatureReply;
+        AllClasses = VirtualMachineAllClasses: () => AllClassesReply;
+        AllThreads = VirtualMachineAllThreads: () => AllThreadsReply;
+        TopLevelThreadGroups = VirtualMachineTopLevelThreadGroups: () => TopLevelThreadGroupsReply;
+        Dispose = VirtualMachineDispose: () => ();
+        IdSizes = VirtualMachineIDSizes: () => IdSizesReply;
+        Suspend = VirtualMachineSuspend: () => ();
+        Resume = VirtualMachineResume: () => ();
+        /// Replies just before the VM terminates, if at all.
+        Exit = VirtualMachineExit: i32 => ();
+        /// Superseded by `CapabilitiesNew` since JDWP 1.4.
+        Capabilities = VirtualMachineCapabilities: () => CapabilitiesReply;
+        /// Gives back references to objects, after which their IDs may be reused.
+        DisposeObjects = VirtualMachineDisposeObjects: DisposeObjectsRequest => ();
+        HoldEvents = VirtualMachineHoldEvents: () => ();
+        ReleaseEvents = VirtualMachineReleaseEvents: () => ();
+        CapabilitiesNew = VirtualMachineCapabilitiesNew: () => crate::jdwp::Capabilities;
+        /// The VM verifies the new class files and patches every frame, which can take a while.
+        RedefineClasses = VirtualMachineRedefineClasses: RedefineClassesRequest => (),
+            timeout = Duration::from_secs(30);
+    }
+}
+
+/// Commands of the ReferenceType command set.
+pub mod reference_type {
+    use super::*;
+
+    jdwp_commands! {
+        Signature = ReferenceTypeSignature: ReferenceTypeId => JdwpString;
+        ClassLoader = ReferenceTypeClassLoader: ReferenceTypeId => ClassLoaderId;
+        /// Replies with the access flags of the type as declared in its class file.
+        Modifiers = ReferenceTypeModifiers: ReferenceTypeId => i32;
+        Fields = ReferenceTypeFields: ReferenceTypeId => FieldsReply;
+        Methods = ReferenceTypeMethods: ReferenceTypeId => MethodsReply;
+        GetValues = ReferenceTypeGetValues: ReferenceTypeGetValuesRequest => ValuesReply;
+        SourceFile = ReferenceTypeSourceFile: ReferenceTypeId => JdwpString;
+        Status = ReferenceTypeStatus: ReferenceTypeId => ClassStatus;
+        Interfaces = ReferenceTypeInterfaces: ReferenceTypeId => InterfacesReply;
+        ClassObject = ReferenceTypeClassObject: ReferenceTypeId => ClassObjectId;
+        FieldsWithGeneric = ReferenceTypeFieldsWithGeneric: ReferenceTypeId => FieldsWithGenericReply;
+        MethodsWithGeneric = ReferenceTypeMethodsWithGeneric:
+            ReferenceTypeId => MethodsWithGenericReply;
+    }
+}
+
+/// Commands of the ClassType command set.
+pub mod class_type {
+    use super::*;
+
+    jdwp_commands! {
+        InvokeMethod = ClassTypeInvokeMethod: StaticInvokeRequest => InvokeMethodReply,
+            timeout = INVOKE_TIMEOUT;
+        NewInstance = ClassTypeNewInstance: StaticInvokeRequest => NewInstanceReply,
+            timeout = INVOKE_TIMEOUT;
+    }
+}
+
+/// Commands of the InterfaceType command set.
+pub mod interface_type {
+    use super::*;
+
+    jdwp_commands! {
+        InvokeMethod = InterfaceTypeInvokeMethod: StaticInvokeRequest => InvokeMethodReply,
+            timeout = INVOKE_TIMEOUT;
+    }
+}
+
+/// Commands of the Method command set.
+pub mod method {
+    use super::*;
+
+    jdwp_commands! {
+        LineTable = MethodLineTable: MethodRequest => LineTableReply;
+        VariableTable = MethodVariableTable: MethodRequest => VariableTableReply;
+        Bytecodes = MethodBytecodes: MethodRequest => BytecodesReply;
+        VariableTableWithGeneric = MethodVariableTableWithGeneric:
+            MethodRequest => VariableTableWithGenericReply;
+    }
+}
+
+/// Commands of the ObjectReference command set.
+pub mod object_reference {
+    use super::*;
+
+    jdwp_commands! {
+        ReferenceType = ObjectReferenceReferenceType: ObjectId => ObjectReferenceTypeReply;
+        GetValues = ObjectReferenceGetValues: ObjectGetValuesRequest => ValuesReply;
+        SetValues = ObjectReferenceSetValues: ObjectSetValuesRequest => ();
+        MonitorInfo = ObjectReferenceMonitorInfo: ObjectId => MonitorInfoReply;
+        InvokeMethod = ObjectReferenceInvokeMethod: ObjectInvokeRequest => InvokeMethodReply,
+            timeout = INVOKE_TIMEOUT;
+        DisableCollection = ObjectReferenceDisableCollection: ObjectId => ();
+        EnableCollection = ObjectReferenceEnableCollection: ObjectId => ();
+    }
+}
+
+/// Commands of the StringReference command set.
+pub mod string_reference {
+    use super::*;
+
+    jdwp_commands! {
+        Value = StringReferenceValue: ObjectId => JdwpString;
+    }
+}
+
+/// Commands of the ThreadReference command set.
+pub mod thread_reference {
+    use super::*;
+
+    jdwp_commands! {
+        Name = ThreadReferenceName: ThreadId => JdwpString;
+        Suspend = ThreadReferenceSuspend: ThreadId => ();
+        Resume = ThreadReferenceResume: ThreadId => ();
+        Status = ThreadReferenceStatus: ThreadId => ThreadStatusReply;
+        ThreadGroup = ThreadReferenceThreadGroup: ThreadId => ThreadGroupId;
+        Frames = ThreadReferenceFrames: ThreadFramesRequest => ThreadFramesReply;
+        FrameCount = ThreadReferenceFrameCount: ThreadId => i32;
+        OwnedMonitors = ThreadReferenceOwnedMonitors: ThreadId => OwnedMonitorsReply;
+        /// Replies with a null object if the thread is not waiting for a monitor.
+        CurrentContendedMonitor = ThreadReferenceCurrentContendedMonitor:
+            ThreadId => TaggedObjectId;
+        SuspendCount = ThreadReferenceSuspendCount: ThreadId => i32;
+        ForceEarlyReturn = ThreadReferenceForceEarlyReturn: ForceEarlyReturnRequest => ();
+    }
+}
+
+/// Commands of the ArrayReference command set.
+pub mod array_reference {
+    use super::*;
+
+    jdwp_commands! {
+        Length = ArrayReferenceLength: ObjectId => i32;
+        GetValues = ArrayReferenceGetValues: ArrayGetValuesRequest => ArrayRegion;
+        SetValues = ArrayReferenceSetValues: ArraySetValuesRequest => ();
+    }
+}
+
+/// Commands of the EventRequest command set.
+pub mod event_request {
+    use super::*;
+
+    jdwp_commands! {
+        Set = EventRequestSet: EventRequestSet => RequestId;
+        Clear = EventRequestClear: EventRequestClear => ();
+        ClearAllBreakpoints = EventRequestClearAllBreakpoints: () => ();
+    }
+}
+
+/// Commands of the StackFrame command set.
+pub mod stack_frame {
+    use super::*;
+
+    jdwp_commands! {
+        GetValues = StackFrameGetValues: StackFrameGetValuesRequest => ValuesReply;
+        SetValues = StackFrameSetValues: StackFrameSetValuesRequest => ();
+        /// Replies with a null object for static and native methods.
+        ThisObject = StackFrameThisObject: StackFrameRequest => TaggedObjectId;
+        PopFrames = StackFramePopFrames: StackFrameRequest => ();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdwp::VariableLengthId;
+
+    const SIZES: JdwpIdSizes = JdwpIdSizes {
+        field_id_size: 8,
+        method_id_size: 8,
+        object_id_size: 4,
+        reference_type_id_size: 8,
+        frame_id_size: 8,
+    };
+
+    #[test]
+    fn test_request_is_encoded_with_id_sizes() {
+        let thread = ThreadId(VariableLengthId { value: 7 });
+        let data = thread_reference::Name::encode_request(&thread, Some(SIZES)).unwrap();
+        assert_eq!(data, [0, 0, 0, 7]);
+
+        let error = thread_reference::Name::encode_request(&thread, None).unwrap_err();
+        assert!(matches!(error, result::Error::IdSizesUnknown));
+    }
+
+    #[test]
+    fn test_bodies_without_ids_need_no_sizes() {
+        let data = virtual_machine::IdSizes::encode_request(&(), None).unwrap();
+        assert!(data.is_empty());
+
+        let reply = [0, 0, 0, 8, 0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 8];
+        let sizes = virtual_machine::IdSizes::decode_reply(&reply, None).unwrap();
+        assert_eq!(sizes.object_id_size, 4);
+    }
+
+    #[test]
+    fn test_request_is_decoded_with_id_sizes() {
+        let request = ThreadFramesRequest {
+            thread: ThreadId(VariableLengthId { value: 7 }),
+            start_frame: 1,
+            length: -1,
+        };
+        let data = thread_reference::Frames::encode_request(&request, Some(SIZES)).unwrap();
+        let decoded = thread_reference::Frames::decode_request(&data, Some(SIZES)).unwrap();
+        assert_eq!(decoded.thread, request.thread);
+        assert_eq!(decoded.start_frame, 1);
+        assert_eq!(decoded.length, -1);
+    }
+
+    #[test]
+    fn test_reply_is_decoded_with_id_sizes() {
+        let reply = [0, 0, 0, 1, 0, 0, 0, 42];
+        let threads = virtual_machine::AllThreads::decode_reply(&reply, Some(SIZES)).unwrap();
+        assert_eq!(threads.threads, [ThreadId(VariableLengthId { value: 42 })]);
+    }
+
+    #[test]
+    fn test_command_pairs() {
+        assert_eq!(virtual_machine::Version::COMMAND.command_set(), 1);
+        assert_eq!(virtual_machine::Version::COMMAND.command(), 1);
+        assert_eq!(stack_frame::PopFrames::COMMAND.command_set(), 16);
+        assert_eq!(stack_frame::PopFrames::COMMAND.command(), 4);
+        assert_eq!(class_type::InvokeMethod::TIMEOUT, INVOKE_TIMEOUT);
+        assert_eq!(thread_reference::Name::TIMEOUT, Duration::from_secs(5));
+    }
+}
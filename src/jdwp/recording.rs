@@ -0,0 +1,417 @@
+use binrw::{BinRead, BinWrite, binrw};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::{Context, Poll, Waker, ready};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::binrw_enum;
+
+const HANDSHAKE_LENGTH: usize = 14;
+
+binrw_enum! {
+    #[repr(u8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Direction {
+        ToVm = 0,
+        FromVm = 1,
+    }
+}
+
+/// A packet of a recorded session. The handshake is recorded as a packet of its own.
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedPacket {
+    /// Microseconds since the Unix epoch.
+    pub timestamp_micros: u64,
+    pub direction: Direction,
+    #[br(temp)]
+    #[bw(calc = data.len() as u32)]
+    data_length: u32,
+    #[br(count = data_length)]
+    pub data: Vec<u8>,
+}
+
+/// Reads all packets of a session written by `RecordingStream`.
+pub fn read_recording<R: io::Read + io::Seek>(reader: &mut R) -> io::Result<Vec<RecordedPacket>> {
+    let mut packets = Vec::new();
+    loop {
+        match RecordedPacket::read(reader) {
+            Ok(packet) => packets.push(packet),
+            Err(e) if e.is_eof() => return Ok(packets),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
+
+/// Splits one direction of a JDWP byte stream into the handshake and packets.
+#[derive(Default)]
+struct PacketFramer {
+    buffer: Vec<u8>,
+    handshake_done: bool,
+}
+impl PacketFramer {
+    fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        loop {
+            let length = if self.handshake_done {
+                let Some(length) = self.buffer.get(0..4) else {
+                    break;
+                };
+                // Never get stuck on a corrupt length
+                (u32::from_be_bytes(length.try_into().unwrap()) as usize).max(4)
+            } else {
+                HANDSHAKE_LENGTH
+            };
+            if self.buffer.len() < length {
+                break;
+            }
+
+            packets.push(self.buffer.drain(..length).collect());
+            self.handshake_done = true;
+        }
+        packets
+    }
+}
+
+/// Wraps the stream of a `JdwpClient` and records every packet going through it to a sink.
+///
+/// The sink is written on a thread of its own, so that it never blocks the runtime. If
+/// writing fails, the error is logged and recording stops, while the stream keeps working.
+pub struct RecordingStream<T> {
+    inner: T,
+    /// Hands encoded packets to the thread writing the sink. `None` once that stopped.
+    packets: Option<mpsc::Sender<Vec<u8>>>,
+    sent: PacketFramer,
+    received: PacketFramer,
+}
+
+impl<T> RecordingStream<T> {
+    /// Records to a new file at `path`, replacing any existing one.
+    pub fn create<P: AsRef<Path>>(inner: T, path: P) -> io::Result<Self> {
+        Ok(Self::new(inner, BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn new<W: Write + Send + 'static>(inner: T, mut sink: W) -> Self {
+        let (packets, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            if let Err(e) = write_recording(&mut sink, &receiver) {
+                log::warn!("Stopped recording, could not write packet: {}", e);
+            }
+        });
+        RecordingStream {
+            inner,
+            packets: Some(packets),
+            sent: PacketFramer::default(),
+            received: PacketFramer::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) {
+        let Some(sender) = &self.packets else {
+            return;
+        };
+        let framer = match direction {
+            Direction::ToVm => &mut self.sent,
+            Direction::FromVm => &mut self.received,
+        };
+        let packets = framer.push(data);
+        if packets.is_empty() {
+            return;
+        }
+
+        let timestamp_micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_micros() as u64)
+            .unwrap_or(0);
+        for data in packets {
+            let mut buffer = Vec::with_capacity(data.len() + 13);
+            let written = RecordedPacket {
+                timestamp_micros,
+                direction,
+                data,
+            }
+            .write(&mut io::Cursor::new(&mut buffer));
+            if let Err(e) = written {
+                log::warn!("Stopped recording, could not encode packet: {}", e);
+                self.packets = None;
+                return;
+            }
+            // Fails once the writer thread stopped, which already logged why
+            if sender.send(buffer).is_err() {
+                self.packets = None;
+                return;
+            }
+        }
+    }
+}
+
+/// Writes the packets of a `RecordingStream` to `sink` until the stream is dropped.
+fn write_recording<W: Write>(sink: &mut W, packets: &mpsc::Receiver<Vec<u8>>) -> io::Result<()> {
+    while let Ok(packet) = packets.recv() {
+        sink.write_all(&packet)?;
+        while let Ok(packet) = packets.try_recv() {
+            sink.write_all(&packet)?;
+        }
+        // Keeps the recording current while the session is idle
+        sink.flush()?;
+    }
+    Ok(())
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for RecordingStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.record(Direction::FromVm, &buf.filled()[filled..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for RecordingStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.record(Direction::ToVm, &buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Plays the VM side of a recorded session back to a `JdwpClient`.
+///
+/// Packets from the VM are handed out in their recorded order, each one only after the
+/// client has sent all packets which preceded it in the recording. The client therefore
+/// has to issue the same commands as the recorded one did; their contents are not checked.
+/// Once the recording is exhausted, reading reports the end of the stream.
+pub struct ReplayStream {
+    packets: Vec<RecordedPacket>,
+    /// Index of the next packet from the VM to hand out.
+    next: usize,
+    /// Offset into the data of the next packet, if it was only read in part.
+    offset: usize,
+    sent_packets: usize,
+    sent: PacketFramer,
+    read_waker: Option<Waker>,
+}
+
+impl ReplayStream {
+    pub fn new(packets: Vec<RecordedPacket>) -> Self {
+        ReplayStream {
+            packets,
+            next: 0,
+            offset: 0,
+            sent_packets: 0,
+            sent: PacketFramer::default(),
+            read_waker: None,
+        }
+    }
+
+    /// Replays a session recorded to `path` by `RecordingStream::create`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Self::new(read_recording(&mut reader)?))
+    }
+
+    /// Finds the next packet from the VM and checks whether the client has caught up to it.
+    fn next_from_vm(&self) -> Option<(usize, bool)> {
+        let mut expected_sent = 0;
+        for (index, packet) in self.packets.iter().enumerate() {
+            match packet.direction {
+                Direction::ToVm => expected_sent += 1,
+                Direction::FromVm if index >= self.next => {
+                    return Some((index, self.sent_packets >= expected_sent));
+                }
+                Direction::FromVm => {}
+            }
+        }
+        None
+    }
+}
+
+impl AsyncRead for ReplayStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let Some((index, ready)) = self.next_from_vm() else {
+            return Poll::Ready(Ok(()));
+        };
+        if !ready {
+            self.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let data = &self.packets[index].data[self.offset..];
+        let length = data.len().min(buf.remaining());
+        buf.put_slice(&data[..length]);
+        if length == data.len() {
+            self.next = index + 1;
+            self.offset = 0;
+        } else {
+            self.offset += length;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ReplayStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let sent = self.sent.push(buf).len();
+        if sent > 0 {
+            self.sent_packets += sent;
+            if let Some(waker) = self.read_waker.take() {
+                waker.wake();
+            }
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdwp::{Command, JdwpClient, MockVm, ThreadId, VariableLengthId};
+    use std::sync::{Arc, Mutex};
+
+    /// Lets the test read what a stream owned by the client recorded.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            io::Write::write(&mut *self.0.lock().unwrap(), buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Fails every write, like a full disk.
+    struct FailingSink;
+    impl Write for FailingSink {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Waits until the writer thread has written at least `count` packets.
+    async fn wait_for_packets(recording: &SharedBuffer, count: usize) -> Vec<RecordedPacket> {
+        for _ in 0..100 {
+            let recorded = recording.0.lock().unwrap().clone();
+            let packets = read_recording(&mut io::Cursor::new(&recorded)).unwrap();
+            if packets.len() >= count {
+                return packets;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("Packets were not recorded");
+    }
+
+    #[test]
+    fn test_framer_splits_handshake_and_packets() {
+        let mut framer = PacketFramer::default();
+        assert!(framer.push(b"JDWP-Hand").is_empty());
+
+        let mut data = b"shake".to_vec();
+        data.extend([0, 0, 0, 11, 0, 0, 0, 1, 0, 1, 1]);
+        data.extend([0, 0, 0, 12]);
+        let packets = framer.push(&data);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], b"JDWP-Handshake");
+        assert_eq!(packets[1].len(), 11);
+
+        let packets = framer.push(&[0, 0, 0, 2, 0, 1, 0, 0]);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].len(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_replay_recorded_session() {
+        let (client_stream, vm_stream) = tokio::io::duplex(64 * 1024);
+        let _vm = MockVm::new()
+            .reply(
+                Command::ThreadReferenceName,
+                vec![0, 0, 0, 4, b'm', b'a', b'i', b'n'],
+            )
+            .spawn(vm_stream);
+
+        let thread = ThreadId(VariableLengthId { value: 1 });
+        let recording = SharedBuffer::default();
+        let stream = RecordingStream::new(client_stream, recording.clone());
+        let client = JdwpClient::new(stream).await.unwrap();
+        let name = client.thread_get_name(thread).await.unwrap();
+        assert_eq!(name.string, "main");
+
+        // Handshake and four commands in each direction
+        let packets = wait_for_packets(&recording, 10).await;
+        assert_eq!(packets.len(), 10);
+        assert_eq!(packets[0].direction, Direction::ToVm);
+        assert_eq!(packets[1].direction, Direction::FromVm);
+
+        let client = JdwpClient::new(ReplayStream::new(packets)).await.unwrap();
+        assert_eq!(client.session().version.vm_name.string, "mock");
+        let name = client.thread_get_name(thread).await.unwrap();
+        assert_eq!(name.string, "main");
+    }
+
+    #[tokio::test]
+    async fn test_sink_error_does_not_fail_the_stream() {
+        let (client_stream, vm_stream) = tokio::io::duplex(64 * 1024);
+        let _vm = MockVm::new()
+            .reply(
+                Command::ThreadReferenceName,
+                vec![0, 0, 0, 4, b'm', b'a', b'i', b'n'],
+            )
+            .spawn(vm_stream);
+
+        let stream = RecordingStream::new(client_stream, FailingSink);
+        let client = JdwpClient::new(stream).await.unwrap();
+        let thread = ThreadId(VariableLengthId { value: 1 });
+        for _ in 0..2 {
+            let name = client.thread_get_name(thread).await.unwrap();
+            assert_eq!(name.string, "main");
+        }
+    }
+}
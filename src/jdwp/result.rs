@@ -1,15 +1,160 @@
-#[derive(Debug)]
-pub enum JdwpErrorCode {}
+macro_rules! jdwp_error_codes {
+    ($($variant:ident = $value:expr => $message:expr),* $(,)?) => {
+        /// Error constants returned by the VM in the header of a reply packet.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum JdwpErrorCode {
+            $($variant,)*
+            Unknown(u16),
+        }
+
+        impl JdwpErrorCode {
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(JdwpErrorCode::$variant => $value,)*
+                    JdwpErrorCode::Unknown(code) => *code,
+                }
+            }
+
+            pub fn message(&self) -> &'static str {
+                match self {
+                    $(JdwpErrorCode::$variant => $message,)*
+                    JdwpErrorCode::Unknown(_) => "Unknown error",
+                }
+            }
+        }
+
+        impl From<u16> for JdwpErrorCode {
+            fn from(value: u16) -> Self {
+                match value {
+                    $($value => JdwpErrorCode::$variant,)*
+                    other => JdwpErrorCode::Unknown(other),
+                }
+            }
+        }
+    };
+}
+
+jdwp_error_codes! {
+    None = 0 => "No error has occurred",
+    InvalidThread = 10 => "Passed thread is null, is not a valid thread or has exited",
+    InvalidThreadGroup = 11 => "Thread group invalid",
+    InvalidPriority = 12 => "Invalid priority",
+    ThreadNotSuspended = 13 => "The specified thread has not been suspended by an event",
+    ThreadSuspended = 14 => "Thread already suspended",
+    ThreadNotAlive = 15 => "Thread has not been started or is now dead",
+    InvalidObject = 20 => "The object is not a known ID or has been garbage collected",
+    InvalidClass = 21 => "Invalid class",
+    ClassNotPrepared = 22 => "Class has been loaded but not yet prepared",
+    InvalidMethodId = 23 => "Invalid method",
+    InvalidLocation = 24 => "Invalid location",
+    InvalidFieldId = 25 => "Invalid field",
+    InvalidFrameId = 30 => "Invalid jframeID",
+    NoMoreFrames = 31 => "There are no more Java or JNI frames on the call stack",
+    OpaqueFrame = 32 => "Information about the frame is not available",
+    NotCurrentFrame = 33 => "Operation can only be performed on current frame",
+    TypeMismatch = 34 => "The variable is not an appropriate type for the function used",
+    InvalidSlot = 35 => "Invalid slot",
+    Duplicate = 40 => "Item already set",
+    NotFound = 41 => "Desired element not found",
+    InvalidModule = 42 => "Invalid module",
+    InvalidMonitor = 50 => "Invalid monitor",
+    NotMonitorOwner = 51 => "This thread doesn't own the monitor",
+    Interrupt = 52 => "The call has been interrupted before completion",
+    InvalidClassFormat = 60 => "The virtual machine attempted to read a class file and determined that the file is malformed",
+    CircularClassDefinition = 61 => "A circularity has been detected while initializing a class",
+    FailsVerification = 62 => "The verifier detected that a class file failed an internal consistency check",
+    AddMethodNotImplemented = 63 => "Adding methods has not been implemented",
+    SchemaChangeNotImplemented = 64 => "Schema change has not been implemented",
+    InvalidTypestate = 65 => "The state of the thread has been modified, and is now inconsistent",
+    HierarchyChangeNotImplemented = 66 => "A direct superclass is different or the set of directly implemented interfaces is different",
+    DeleteMethodNotImplemented = 67 => "The new class version does not declare a method declared in the old class version",
+    UnsupportedVersion = 68 => "A class file has a version number not supported by this VM",
+    NamesDontMatch = 69 => "The class name defined in the new class file is different from the name in the old class object",
+    ClassModifiersChangeNotImplemented = 70 => "The new class version has different modifiers",
+    MethodModifiersChangeNotImplemented = 71 => "A method in the new class version has different modifiers than its counterpart in the old class version",
+    ClassAttributeChangeNotImplemented = 72 => "The new class version has a different NestHost, NestMembers, PermittedSubclasses, or Record class attribute",
+    NotImplemented = 99 => "The functionality is not implemented in this virtual machine",
+    NullPointer = 100 => "Invalid pointer",
+    AbsentInformation = 101 => "Desired information is not available",
+    InvalidEventType = 102 => "The specified event type id is not recognized",
+    IllegalArgument = 103 => "Illegal argument",
+    OutOfMemory = 110 => "The function needed to allocate memory and no more memory was available for allocation",
+    AccessDenied = 111 => "Debugging has not been enabled in this virtual machine",
+    VmDead = 112 => "The virtual machine is not running",
+    Internal = 113 => "An unexpected internal error has occurred",
+    UnattachedThread = 115 => "The thread being used to call this function is not attached to the virtual machine",
+    InvalidTag = 500 => "Object type id or class tag",
+    AlreadyInvoking = 502 => "Previous invoke not complete",
+    InvalidIndex = 503 => "Index is invalid",
+    InvalidLength = 504 => "The length is invalid",
+    InvalidString = 506 => "The string is invalid",
+    InvalidClassLoader = 507 => "The class loader is invalid",
+    InvalidArray = 508 => "The array is invalid",
+    TransportLoad = 509 => "Unable to load the transport",
+    TransportInit = 510 => "Unable to initialize the transport",
+    NativeMethod = 511 => "Native method",
+    InvalidCount = 512 => "The count is invalid",
+}
+
+impl std::fmt::Display for JdwpErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (JDWP error {})", self.message(), self.code())
+    }
+}
 
 #[derive(Debug)]
 pub enum Error {
     IoError(std::io::Error),
     JdwpError(JdwpErrorCode),
-    ParsingError { message: String },
+    ParsingError {
+        message: String,
+    },
     IdSizesUnknown,
     IdSizesTruncated,
+    /// The VM lacks a capability required by the command, e.g. `canRedefineClasses`.
+    CapabilityMissing(&'static str),
+    /// No loaded class matches the given type signature.
+    ClassNotLoaded {
+        signature: String,
+    },
+    /// The reference type declares no field with the given name.
+    FieldNotFound {
+        name: String,
+    },
+    /// The connection to the VM was lost.
+    Disconnected,
+    /// The VM sent a VMDeath event and no longer answers commands.
+    VmDead,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IoError(error) => write!(f, "I/O error: {}", error),
+            Error::JdwpError(code) => write!(f, "VM returned an error: {}", code),
+            Error::ParsingError { message } => write!(f, "{}", message),
+            Error::IdSizesUnknown => write!(f, "ID sizes have not been negotiated with the VM"),
+            Error::IdSizesTruncated => {
+                write!(
+                    f,
+                    "ID sizes reported by the VM are not between 1 and 8 bytes"
+                )
+            }
+            Error::CapabilityMissing(capability) => {
+                write!(f, "VM does not have the {} capability", capability)
+            }
+            Error::ClassNotLoaded { signature } => {
+                write!(f, "Class {} is not loaded in the VM", signature)
+            }
+            Error::FieldNotFound { name } => write!(f, "Field {} not found", name),
+            Error::Disconnected => write!(f, "Connection to the VM was lost"),
+            Error::VmDead => write!(f, "The VM has terminated"),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl From<std::io::Error> for Error {
@@ -17,3 +162,31 @@ impl From<std::io::Error> for Error {
         Error::IoError(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code_from_u16() {
+        assert_eq!(JdwpErrorCode::from(10), JdwpErrorCode::InvalidThread);
+        assert_eq!(JdwpErrorCode::from(112), JdwpErrorCode::VmDead);
+        assert_eq!(JdwpErrorCode::from(512), JdwpErrorCode::InvalidCount);
+    }
+
+    #[test]
+    fn test_error_code_unknown() {
+        let code = JdwpErrorCode::from(1234);
+        assert_eq!(code, JdwpErrorCode::Unknown(1234));
+        assert_eq!(code.code(), 1234);
+    }
+
+    #[test]
+    fn test_error_code_display() {
+        let code = JdwpErrorCode::from(101);
+        assert_eq!(
+            code.to_string(),
+            "Desired information is not available (JDWP error 101)"
+        );
+    }
+}
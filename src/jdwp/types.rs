@@ -1,4 +1,7 @@
 use binrw::{BinRead, BinWrite};
+use std::io::SeekFrom;
+
+use crate::jdwp::{Tag, TypeTag, VariableLengthId};
 
 pub type JdwpIdSize = u8;
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,6 +62,237 @@ impl BinWrite for JdwpString {
     }
 }
 
+/// Upper bound on the entries preallocated for a count read from a packet. Longer
+/// sections still decode, the vector just grows as entries are read.
+const MAX_PREALLOCATED_ENTRIES: usize = 1024;
+
+/// Reads the `int` count which prefixes a repeated section of a packet, rejecting
+/// negative counts.
+pub(crate) fn read_count<R: std::io::Read + std::io::Seek>(
+    reader: &mut R,
+    endian: binrw::Endian,
+) -> binrw::BinResult<usize> {
+    let pos = reader.stream_position()?;
+    let count = i32::read_options(reader, endian, ())?;
+    usize::try_from(count).map_err(|_| binrw::Error::AssertFail {
+        pos,
+        message: format!("Negative count {}", count),
+    })
+}
+
+/// Creates a vector for `count` entries without trusting the VM to send that many.
+pub(crate) fn vec_for_count<T>(count: usize) -> Vec<T> {
+    Vec::with_capacity(count.min(MAX_PREALLOCATED_ENTRIES))
+}
+
+/// Declares a newtype over `VariableLengthId` which reads and writes itself using the
+/// given field of `JdwpIdSizes`.
+macro_rules! jdwp_id {
+    ($(#[$meta:meta])* $name:ident, $size:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name(pub VariableLengthId);
+
+        impl $name {
+            /// Whether this is the null ID 0, which the VM uses e.g. for a null reference.
+            pub fn is_null(&self) -> bool {
+                self.0.value == 0
+            }
+        }
+
+        impl BinRead for $name {
+            type Args<'a> = JdwpIdSizes;
+
+            fn read_options<R: std::io::Read + std::io::Seek>(
+                reader: &mut R,
+                endian: binrw::Endian,
+                args: Self::Args<'_>,
+            ) -> binrw::BinResult<Self> {
+                Ok($name(VariableLengthId::read_options(reader, endian, args.$size)?))
+            }
+        }
+
+        impl BinWrite for $name {
+            type Args<'a> = JdwpIdSizes;
+
+            fn write_options<W: std::io::Write + std::io::Seek>(
+                &self,
+                writer: &mut W,
+                endian: binrw::Endian,
+                args: Self::Args<'_>,
+            ) -> binrw::BinResult<()> {
+                self.0.write_options(writer, endian, args.$size)
+            }
+        }
+    };
+}
+
+jdwp_id!(
+    /// Any object in the VM.
+    ObjectId,
+    object_id_size
+);
+jdwp_id!(
+    /// A `java.lang.Thread` object.
+    ThreadId,
+    object_id_size
+);
+jdwp_id!(
+    /// A `java.lang.ThreadGroup` object.
+    ThreadGroupId,
+    object_id_size
+);
+jdwp_id!(
+    /// A class, interface or array type loaded by the VM.
+    ReferenceTypeId,
+    reference_type_id_size
+);
+jdwp_id!(
+    /// A field of a reference type. Only unique together with the declaring type.
+    FieldId,
+    field_id_size
+);
+jdwp_id!(
+    /// A method of a reference type. Only unique together with the declaring type.
+    MethodId,
+    method_id_size
+);
+jdwp_id!(
+    /// A `java.lang.ClassLoader` object. The bootstrap loader has the ID 0.
+    ClassLoaderId,
+    object_id_size
+);
+jdwp_id!(
+    /// A `java.lang.Class` object.
+    ClassObjectId,
+    object_id_size
+);
+jdwp_id!(
+    /// A stack frame of a suspended thread. Only valid until the thread is resumed.
+    FrameId,
+    frame_id_size
+);
+
+// Threads, thread groups, class loaders and class objects can be used as plain objects,
+// e.g. to read their fields
+impl From<ThreadId> for ObjectId {
+    fn from(id: ThreadId) -> Self {
+        ObjectId(id.0)
+    }
+}
+impl From<ThreadGroupId> for ObjectId {
+    fn from(id: ThreadGroupId) -> Self {
+        ObjectId(id.0)
+    }
+}
+impl From<ClassLoaderId> for ObjectId {
+    fn from(id: ClassLoaderId) -> Self {
+        ObjectId(id.0)
+    }
+}
+impl From<ClassObjectId> for ObjectId {
+    fn from(id: ClassObjectId) -> Self {
+        ObjectId(id.0)
+    }
+}
+
+/// An executable location: a code index inside a method of a class or interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub type_tag: TypeTag,
+    pub class_id: ReferenceTypeId,
+    pub method_id: MethodId,
+    pub index: u64,
+}
+impl Location {
+    /// Reads a location which the VM may send zeroed out, e.g. the catch location
+    /// of an uncaught exception.
+    pub fn read_optional<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+    ) -> binrw::BinResult<Option<Self>> {
+        let tag = u8::read_options(reader, endian, ())?;
+        reader.seek(SeekFrom::Current(-1))?;
+        if tag == 0 {
+            u8::read_options(reader, endian, ())?;
+            ReferenceTypeId::read_options(reader, endian, args)?;
+            MethodId::read_options(reader, endian, args)?;
+            u64::read_options(reader, endian, ())?;
+            return Ok(None);
+        }
+
+        Ok(Some(Location::read_options(reader, endian, args)?))
+    }
+}
+impl BinRead for Location {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(Location {
+            type_tag: TypeTag::read_options(reader, endian, ())?,
+            class_id: ReferenceTypeId::read_options(reader, endian, args)?,
+            method_id: MethodId::read_options(reader, endian, args)?,
+            index: u64::read_options(reader, endian, ())?,
+        })
+    }
+}
+impl BinWrite for Location {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.type_tag.write_options(writer, endian, ())?;
+        self.class_id.write_options(writer, endian, args)?;
+        self.method_id.write_options(writer, endian, args)?;
+        self.index.write_options(writer, endian, ())
+    }
+}
+
+/// An object ID preceded by the tag of the object's runtime type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaggedObjectId {
+    pub tag: Tag,
+    pub object_id: ObjectId,
+}
+impl TaggedObjectId {
+    /// Reads a tagged object ID which the VM sends as 0 for a null reference.
+    pub fn read_optional<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+    ) -> binrw::BinResult<Option<Self>> {
+        let object = TaggedObjectId::read_options(reader, endian, args)?;
+        if object.object_id.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(object))
+    }
+}
+impl BinRead for TaggedObjectId {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Ok(TaggedObjectId {
+            tag: Tag::read_options(reader, endian, ())?,
+            object_id: ObjectId::read_options(reader, endian, args)?,
+        })
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Cursor;
@@ -104,4 +338,32 @@ mod tests {
         let expected = [0u8, 0u8, 0u8, 0u8]; // 0 length
         assert_eq!(buffer, expected);
     }
+
+    #[test]
+    fn test_location_ids_use_their_own_sizes() {
+        let sizes = JdwpIdSizes {
+            field_id_size: 8,
+            method_id_size: 2,
+            object_id_size: 4,
+            reference_type_id_size: 3,
+            frame_id_size: 8,
+        };
+        let data = [1, 0, 0, 5, 0, 6, 0, 0, 0, 0, 0, 0, 0, 7];
+        let location = Location::read_be_args(&mut Cursor::new(&data), sizes).unwrap();
+        assert_eq!(
+            location.class_id,
+            ReferenceTypeId(VariableLengthId { value: 5 })
+        );
+        assert_eq!(location.method_id, MethodId(VariableLengthId { value: 6 }));
+        assert_eq!(location.index, 7);
+
+        let null = [b'L', 0, 0, 0, 0];
+        let object = TaggedObjectId::read_be_args(&mut Cursor::new(&null), sizes).unwrap();
+        assert!(object.object_id.is_null());
+        let thread = ThreadId(VariableLengthId { value: 9 });
+        assert_eq!(
+            ObjectId::from(thread),
+            ObjectId(VariableLengthId { value: 9 })
+        );
+    }
 }
@@ -0,0 +1,203 @@
+use binrw::{BinRead, BinWrite};
+
+use crate::jdwp::{
+    ClassLoaderId, ClassObjectId, JdwpIdSizes, ObjectId, Tag, ThreadGroupId, ThreadId,
+};
+
+/// A value as transferred over JDWP. Object values carry the ID of the object,
+/// their tag specifies the runtime type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Array(ObjectId),
+    Byte(i8),
+    Char(u16),
+    Object(ObjectId),
+    Float(f32),
+    Double(f64),
+    Int(i32),
+    Long(i64),
+    Short(i16),
+    Void,
+    Boolean(bool),
+    String(ObjectId),
+    Thread(ThreadId),
+    ThreadGroup(ThreadGroupId),
+    ClassLoader(ClassLoaderId),
+    ClassObject(ClassObjectId),
+}
+impl Value {
+    pub fn tag(&self) -> Tag {
+        match self {
+            Value::Array(_) => Tag::Array,
+            Value::Byte(_) => Tag::Byte,
+            Value::Char(_) => Tag::Char,
+            Value::Object(_) => Tag::Object,
+            Value::Float(_) => Tag::Float,
+            Value::Double(_) => Tag::Double,
+            Value::Int(_) => Tag::Int,
+            Value::Long(_) => Tag::Long,
+            Value::Short(_) => Tag::Short,
+            Value::Void => Tag::Void,
+            Value::Boolean(_) => Tag::Boolean,
+            Value::String(_) => Tag::String,
+            Value::Thread(_) => Tag::Thread,
+            Value::ThreadGroup(_) => Tag::ThreadGroup,
+            Value::ClassLoader(_) => Tag::ClassLoader,
+            Value::ClassObject(_) => Tag::ClassObject,
+        }
+    }
+
+    /// Returns the object ID if this is a reference value.
+    pub fn object_id(&self) -> Option<ObjectId> {
+        match self {
+            Value::Array(id) | Value::Object(id) | Value::String(id) => Some(*id),
+            Value::Thread(id) => Some((*id).into()),
+            Value::ThreadGroup(id) => Some((*id).into()),
+            Value::ClassLoader(id) => Some((*id).into()),
+            Value::ClassObject(id) => Some((*id).into()),
+            _ => None,
+        }
+    }
+
+    /// Reads a value whose tag is known up front, e.g. from a field signature
+    /// or the component type of a primitive array.
+    pub fn read_untagged<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        tag: Tag,
+        args: JdwpIdSizes,
+    ) -> binrw::BinResult<Self> {
+        let object_id = |reader: &mut R| ObjectId::read_options(reader, endian, args);
+
+        Ok(match tag {
+            Tag::Array => Value::Array(object_id(reader)?),
+            Tag::Byte => Value::Byte(i8::read_options(reader, endian, ())?),
+            Tag::Char => Value::Char(u16::read_options(reader, endian, ())?),
+            Tag::Object => Value::Object(object_id(reader)?),
+            Tag::Float => Value::Float(f32::read_options(reader, endian, ())?),
+            Tag::Double => Value::Double(f64::read_options(reader, endian, ())?),
+            Tag::Int => Value::Int(i32::read_options(reader, endian, ())?),
+            Tag::Long => Value::Long(i64::read_options(reader, endian, ())?),
+            Tag::Short => Value::Short(i16::read_options(reader, endian, ())?),
+            Tag::Void => Value::Void,
+            Tag::Boolean => Value::Boolean(u8::read_options(reader, endian, ())? != 0),
+            Tag::String => Value::String(object_id(reader)?),
+            Tag::Thread => Value::Thread(ThreadId::read_options(reader, endian, args)?),
+            Tag::ThreadGroup => {
+                Value::ThreadGroup(ThreadGroupId::read_options(reader, endian, args)?)
+            }
+            Tag::ClassLoader => {
+                Value::ClassLoader(ClassLoaderId::read_options(reader, endian, args)?)
+            }
+            Tag::ClassObject => {
+                Value::ClassObject(ClassObjectId::read_options(reader, endian, args)?)
+            }
+        })
+    }
+
+    /// Writes the value without its tag.
+    pub fn write_untagged<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: JdwpIdSizes,
+    ) -> binrw::BinResult<()> {
+        match self {
+            Value::Array(id) | Value::Object(id) | Value::String(id) => {
+                id.write_options(writer, endian, args)
+            }
+            Value::Byte(v) => v.write_options(writer, endian, ()),
+            Value::Char(v) => v.write_options(writer, endian, ()),
+            Value::Float(v) => v.write_options(writer, endian, ()),
+            Value::Double(v) => v.write_options(writer, endian, ()),
+            Value::Int(v) => v.write_options(writer, endian, ()),
+            Value::Long(v) => v.write_options(writer, endian, ()),
+            Value::Short(v) => v.write_options(writer, endian, ()),
+            Value::Void => Ok(()),
+            Value::Boolean(v) => (*v as u8).write_options(writer, endian, ()),
+            Value::Thread(id) => id.write_options(writer, endian, args),
+            Value::ThreadGroup(id) => id.write_options(writer, endian, args),
+            Value::ClassLoader(id) => id.write_options(writer, endian, args),
+            Value::ClassObject(id) => id.write_options(writer, endian, args),
+        }
+    }
+}
+impl BinRead for Value {
+    type Args<'a> = JdwpIdSizes;
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        let tag = Tag::read_options(reader, endian, ())?;
+        Value::read_untagged(reader, endian, tag, args)
+    }
+}
+impl BinWrite for Value {
+    type Args<'a> = JdwpIdSizes;
+
+    fn write_options<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        args: Self::Args<'_>,
+    ) -> binrw::BinResult<()> {
+        self.tag().write_options(writer, endian, ())?;
+        self.write_untagged(writer, endian, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdwp::VariableLengthId;
+    use std::io::Cursor;
+
+    const SIZES: JdwpIdSizes = JdwpIdSizes {
+        field_id_size: 8,
+        method_id_size: 8,
+        object_id_size: 4,
+        reference_type_id_size: 8,
+        frame_id_size: 8,
+    };
+
+    #[test]
+    fn test_read_int_value() {
+        let data = [b'I', 0, 0, 1, 0];
+        let mut cursor = Cursor::new(&data);
+        let value = Value::read_be_args(&mut cursor, SIZES).unwrap();
+        assert_eq!(value, Value::Int(256));
+    }
+
+    #[test]
+    fn test_read_object_value_uses_object_id_size() {
+        let data = [b's', 0, 0, 0, 42];
+        let mut cursor = Cursor::new(&data);
+        let value = Value::read_be_args(&mut cursor, SIZES).unwrap();
+        let id = ObjectId(VariableLengthId { value: 42 });
+        assert_eq!(value, Value::String(id));
+        assert_eq!(value.object_id(), Some(id));
+    }
+
+    #[test]
+    fn test_write_long_value() {
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        Value::Long(-1).write_be_args(&mut cursor, SIZES).unwrap();
+        assert_eq!(
+            buffer,
+            [b'J', 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_write_array_value() {
+        let mut buffer: Vec<u8> = vec![];
+        let mut cursor = Cursor::new(&mut buffer);
+        Value::Array(ObjectId(VariableLengthId { value: 7 }))
+            .write_be_args(&mut cursor, SIZES)
+            .unwrap();
+        assert_eq!(buffer, [b'[', 0, 0, 0, 7]);
+    }
+}